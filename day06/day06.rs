@@ -2,6 +2,9 @@ use std::fs;
 use std::fs::File;
 use std::io::{self, BufWriter, Write};
 use std::collections::HashSet;
+use rayon::prelude::*;
+
+type State = ((i32, i32), (i32, i32)); // (position, direction)
 
 fn read_input(filename: &str) -> Vec<Vec<char>> {
     let mut output: Vec<Vec<char>> = Vec::new();
@@ -10,106 +13,99 @@ fn read_input(filename: &str) -> Vec<Vec<char>> {
         let char_array = line.chars().collect();
         output.push(char_array);
     }
-    return output
+    output
 }
 
 /// I should write a generic Grid class for AoC...
 /// It'll also be good to about Rust classes, etc.
 /// This ideally should be a separate importable module (crate?) but this is fine :)
+/// Positions are (x, y) = (column, row) and (0, 0) is top left, so maps don't have to be square.
 struct Grid {
-    width: i32,
-    height: i32,
-    objects: Vec<(i32, i32)>, // (0, 0) is top left
+    width: i32,  // Number of columns (x goes from 0 to width - 1)
+    height: i32, // Number of rows (y goes from 0 to height - 1)
+    objects: HashSet<(i32, i32)>,
     walked: Vec<(i32, i32)>,
     done: bool
 }
 
+impl Grid {
+    fn turn(&self, direction: (i32, i32)) -> (i32, i32) {
+        // y points down, so turning right takes up (0, -1) to right (1, 0)
+        (-direction.1, direction.0)
+    }
+}
+
 impl Grid {
     fn walk_until_object(&mut self, start_pos: (i32, i32), direction: (i32, i32)) -> ((i32, i32), (i32, i32)) {
         // Returns ((x, y), dir)
         // Direction should be (1, 0), (-1, 0), (0, 1), or (0, -1) :)
         assert!(((direction.0 + direction.1).abs() == 1) && (direction.0 * direction.1 == 0));
-        let next_direction = (-1*direction.1, direction.0);
+        let next_direction = self.turn(direction);
         let mut flag = false;
-        if direction.0 != 0 { // Look for objects on the same row
-            let obstructions: Vec<(i32, i32)> = self.objects.iter().filter(|&(x, _)| x == &start_pos.0).map(|&(x, y)| (x, y)).collect();
-            let mut obstructions_y: Vec<i32> = obstructions.into_iter().map(|(_, y)| y).collect();
-            let hit_y: i32;
-            // Keep only values greater or less than start_pos.1
-            obstructions_y = obstructions_y
-                .into_iter()
-                .filter(|&y| if direction.0 < 0 { y < start_pos.1 } else { y > start_pos.1 })
-                .collect();
-            if obstructions_y.len() == 0 {
+        if direction.0 != 0 { // Moving along x, so look for objects on the same row
+            let mut obstructions_x: Vec<i32> = self.objects.iter().filter(|&(_, y)| y == &start_pos.1).map(|&(x, _)| x).collect();
+            let hit_x: i32;
+            // Keep only values greater or less than start_pos.0
+            obstructions_x.retain(|&x| if direction.0 < 0 { x < start_pos.0 } else { x > start_pos.0 });
+            if obstructions_x.is_empty() {
                 // No obstructions; guard's leaving but we still have to mark things down!
                 self.done = true;
-                hit_y = if direction.0 < 0 { 0 } else { self.height - 1};
+                hit_x = if direction.0 < 0 { 0 } else { self.width - 1 };
+            } else if direction.0 < 0 {
+                hit_x = obstructions_x.into_iter().max().unwrap() + 1 // We stop one away from it!
             } else {
-                if direction.0 < 0 {
-                    hit_y = obstructions_y.into_iter().max().unwrap() + 1 // We stop one away from it!
-                } else {
-                    hit_y = obstructions_y.into_iter().min().unwrap() - 1
-                }
+                // We want the MIN! if we're going right :)
+                hit_x = obstructions_x.into_iter().min().unwrap() - 1
             }
             // Mark self.walked! (Rust doesn't allow backwards ranges!!)
-            for y in std::cmp::min(start_pos.1, hit_y)..=std::cmp::max(start_pos.1, hit_y) {
+            for x in std::cmp::min(start_pos.0, hit_x)..=std::cmp::max(start_pos.0, hit_x) {
                 flag = true;
-                self.walked.push((start_pos.0, y));
+                self.walked.push((x, start_pos.1));
             }
             if !flag {
-                println!("Problem between {:?} and {:?}", start_pos, (start_pos.0, hit_y))
+                println!("Problem between {:?} and {:?}", start_pos, (hit_x, start_pos.1))
             }
-            return ((start_pos.0, hit_y), next_direction)
+            ((hit_x, start_pos.1), next_direction)
 
-        } else { // Look for objects on the same column
-            let obstructions: Vec<(i32, i32)> = self.objects.iter().filter(|&(_, y)| y == &start_pos.1).map(|&(x, y)| (x, y)).collect();
-            let mut obstructions_x: Vec<i32> = obstructions.into_iter().map(|(x, _)| x).collect();
-            let hit_x: i32;
-            // Keep only values greater or less than start_pos.0
-            obstructions_x = obstructions_x
-                .into_iter()
-                .filter(|&x| if direction.1 < 0 { x < start_pos.0 } else { x > start_pos.0 })
-                .collect();
-            if obstructions_x.len() == 0 {
+        } else { // Moving along y, so look for objects on the same column
+            let mut obstructions_y: Vec<i32> = self.objects.iter().filter(|&(x, _)| x == &start_pos.0).map(|&(_, y)| y).collect();
+            let hit_y: i32;
+            // Keep only values greater or less than start_pos.1
+            obstructions_y.retain(|&y| if direction.1 < 0 { y < start_pos.1 } else { y > start_pos.1 });
+            if obstructions_y.is_empty() {
                 // No obstructions; guard's leaving but we still have to mark things down!
                 self.done = true;
-                hit_x = if direction.1 < 0 { 0 } else { self.width - 1};
+                hit_y = if direction.1 < 0 { 0 } else { self.height - 1 };
+            } else if direction.1 < 0 {
+                hit_y = obstructions_y.into_iter().max().unwrap() + 1; // We stop one away from it!
             } else {
-                // Go up to the obstruction
-                if direction.1 < 1 {
-                    hit_x = obstructions_x.into_iter().max().unwrap() + 1; // We stop one away from it!
-                } else {
-                    // We want the MIN! if we're going right/down :)
-                    hit_x = obstructions_x.into_iter().min().unwrap() - 1
-                }
-                
+                // We want the MIN! if we're going down :)
+                hit_y = obstructions_y.into_iter().min().unwrap() - 1
             }
-            for x in std::cmp::min(start_pos.0, hit_x)..=std::cmp::max(start_pos.0, hit_x){ 
+            for y in std::cmp::min(start_pos.1, hit_y)..=std::cmp::max(start_pos.1, hit_y) {
                 flag = true;
-                self.walked.push((x, start_pos.1));
+                self.walked.push((start_pos.0, y));
             }
             if !flag {
-                println!("Problem between {:?} and {:?}", start_pos, (hit_x, start_pos.1))
+                println!("Problem between {:?} and {:?}", start_pos, (start_pos.0, hit_y))
             }
-            return ((hit_x, start_pos.1), next_direction)
+            ((start_pos.0, hit_y), next_direction)
         }
     }
 }
 
 impl Grid {
+    #[allow(dead_code)] // Only used when I'm debugging the walk :)
     fn write_to_txt(&self, fname: String) -> io::Result<()> {
         let file = File::create(fname)?;
         let mut writer = BufWriter::new(file);
-        for row in 0..self.height {
+        let walked: HashSet<(i32, i32)> = self.walked.iter().cloned().collect();
+        for y in 0..self.height {
             let mut line: Vec<char> = Vec::new();
-            let obstructions: Vec<(i32, i32)> = self.objects.iter().filter(|&(x, _)| x == &row).map(|&(x, y)| (x, y)).collect();
-            let obstructions_y: Vec<i32> = obstructions.into_iter().map(|(_, y)| y).collect();
-            let walked: Vec<(i32, i32)> = self.walked.iter().filter(|&(x, _)| x == &row).map(|&(x, y)| (x, y)).collect();
-            let walked_y: Vec<i32> = walked.into_iter().map(|(_, y)| y).collect();
-            for col in 0..self.width {
-                if obstructions_y.contains(&col) {
+            for x in 0..self.width {
+                if self.objects.contains(&(x, y)) {
                     line.push('#');
-                } else if walked_y.contains(&col) {
+                } else if walked.contains(&(x, y)) {
                     line.push('X');
                 } else {
                     line.push('.');
@@ -124,31 +120,279 @@ impl Grid {
 }
 
 
-fn part1() {
-    let input = read_input("input.txt");
-    let mut map = Grid {
-        width: input.len() as i32,
-        height: input[0].len() as i32,
-        objects: Vec::new(),
-        walked: Vec::new(),
-        done: false
-    };
+/// Guards can start facing any way: ^ > v <
+/// Directions are (dx, dy) like in walk_until_object, so up is (0, -1).
+fn parse_direction(c: char) -> Option<(i32, i32)> {
+    match c {
+        '^' => Some((0, -1)),
+        '>' => Some((1, 0)),
+        'v' => Some((0, 1)),
+        '<' => Some((-1, 0)),
+        _ => None
+    }
+}
+
+fn direction_char(direction: (i32, i32)) -> char {
+    match direction {
+        (0, -1) => '^',
+        (1, 0) => '>',
+        (0, 1) => 'v',
+        (-1, 0) => '<',
+        _ => '?'
+    }
+}
+
+impl Grid {
+    /// Parses the map once. Returns the grid and every guard's starting (position, direction) in reading order.
+    fn from_input(input: &[Vec<char>]) -> (Grid, Vec<State>) {
+        let mut map = Grid {
+            width: input[0].len() as i32,
+            height: input.len() as i32,
+            objects: HashSet::new(),
+            walked: Vec::new(),
+            done: false
+        };
+        let mut guards: Vec<State> = Vec::new();
+
+        // Populate the map with "#" as objects
+        for (y, row) in input.iter().enumerate() {
+            assert!(row.len() == input[0].len(), "Row {y} is a different length from the first row!");
+            for (x, &c) in row.iter().enumerate() {
+                if c == '#' { // Use '' for char, "" for string!
+                    map.objects.insert((x as i32, y as i32));
+                }
+                // Might as well look for the guards while we're looping :)
+                if let Some(direction) = parse_direction(c) {
+                    guards.push(((x as i32, y as i32), direction));
+                }
+            }
+        }
+        (map, guards)
+    }
+}
+
+// Single-step simulation for part 2. walk_until_object jumps straight to the next obstruction,
+// but here we want to stop at every (position, direction) so we can tell if we've looped.
+impl Grid {
+    fn in_bounds(&self, pos: (i32, i32)) -> bool {
+        (pos.0 >= 0) && (pos.0 < self.width) && (pos.1 >= 0) && (pos.1 < self.height)
+    }
 
-    let mut guard_position: (i32, i32) = (0, 0); // So the compiler doesn't yell at me for an uninitialized var
-    let mut guard_direction: (i32, i32) = (0, -1); // Starts facing up
+    fn is_blocked(&self, pos: (i32, i32), extra_object: Option<(i32, i32)>) -> bool {
+        self.objects.contains(&pos) || extra_object == Some(pos)
+    }
+
+    /// Moves the guard one step (or turns in place). Returns None once the guard walks off the map.
+    fn step(&self, pos: (i32, i32), direction: (i32, i32), extra_object: Option<(i32, i32)>) -> Option<State> {
+        let next_pos = (pos.0 + direction.0, pos.1 + direction.1);
+        if !self.in_bounds(next_pos) {
+            return None
+        }
+        if self.is_blocked(next_pos, extra_object) {
+            return Some((pos, self.turn(direction)))
+        }
+        Some((next_pos, direction))
+    }
+
+    /// Every (position, direction) the guard is in until leaving the map, starting with the initial one.
+    /// None if the guard never leaves (some maps trap the guard without any help from us!)
+    fn guard_path(&self, start_pos: (i32, i32), direction: (i32, i32)) -> Option<Vec<State>> {
+        let mut path = vec![(start_pos, direction)];
+        let mut seen: HashSet<State> = HashSet::from([(start_pos, direction)]);
+        let mut state = (start_pos, direction);
+        while let Some(next_state) = self.step(state.0, state.1, None) {
+            if !seen.insert(next_state) {
+                return None
+            }
+            path.push(next_state);
+            state = next_state;
+        }
+        Some(path)
+    }
+
+    /// Does the guard get stuck in a loop with one extra obstruction on the map?
+    fn loops_with(&self, start_pos: (i32, i32), direction: (i32, i32), extra_object: (i32, i32)) -> bool {
+        let mut seen: HashSet<State> = HashSet::new();
+        let mut state = (start_pos, direction);
+        while seen.insert(state) {
+            match self.step(state.0, state.1, Some(extra_object)) {
+                Some(next_state) => state = next_state,
+                None => return false
+            }
+        }
+        true // We've been here before (facing the same way!)
+    }
 
-    // Populate the map with "#" as objects
-    for i in 0..map.width {
-        for j in 0..map.height {
-            if input[i as usize][j as usize] == '#' { // Use '' for char, "" for string!
-                map.objects.push((i, j));
+    /// All the places a single new obstruction would trap the guard in a loop (sorted).
+    /// None if the guard is already stuck in a loop without one.
+    fn find_loop_positions(&self, start_pos: (i32, i32), direction: (i32, i32)) -> Option<Vec<(i32, i32)>> {
+        // An obstruction off the original path never gets hit, so those are the only candidates.
+        // We only care about the FIRST time the guard reaches each cell: everything before that is
+        // unchanged by the new obstruction, so the trial can resume from the state just before it.
+        let path = self.guard_path(start_pos, direction)?;
+        let mut tried: HashSet<(i32, i32)> = HashSet::new();
+        let mut candidates: Vec<((i32, i32), State)> = Vec::new();
+        for i in 1..path.len() {
+            let pos = path[i].0;
+            if pos != start_pos && tried.insert(pos) { // Still not allowed to put it on the guard!
+                candidates.push((pos, path[i-1]));
             }
-            // Might as well look for the guard "^" while we're looping :)
-            if input[i as usize][j as usize] == '^' {
-                guard_position = (i, j);
+        }
+
+        let mut loop_positions: Vec<(i32, i32)> = candidates
+            .par_iter()
+            .filter(|(obstruction, (pos, dir))| self.loops_with(*pos, *dir, *obstruction))
+            .map(|(obstruction, _)| *obstruction)
+            .collect();
+        loop_positions.sort();
+        Some(loop_positions)
+    }
+}
+
+/// What happens when a guard tries to walk into another guard.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Collisions {
+    Ignore, // They just walk through each other
+    Block,  // Other guards count as obstructions, so you turn right
+    Stop    // Both guards stop where they are for good
+}
+
+impl Collisions {
+    fn from_args() -> Collisions {
+        // e.g. `cargo run -- --collisions=block`
+        match std::env::args().find_map(|arg| arg.strip_prefix("--collisions=").map(|s| s.to_string())) {
+            None => Collisions::Ignore,
+            Some(rule) => match rule.as_str() {
+                "ignore" => Collisions::Ignore,
+                "block" => Collisions::Block,
+                "stop" => Collisions::Stop,
+                _ => panic!("Unknown collision rule {rule} (try ignore, block, or stop)")
             }
         }
     }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Outcome {
+    Exited(State),   // Last (position, direction) on the map before walking off
+    Collided(usize), // Stopped after running into this guard
+    Looped           // Never leaves :(
+}
+
+#[derive(Debug)]
+struct GuardReport {
+    start: State,
+    walked: HashSet<(i32, i32)>,
+    steps: usize,
+    outcome: Outcome
+}
+
+impl Grid {
+    /// Walks every guard at the same time. Each tick, guards take turns moving in reading order,
+    /// so a guard sees where the earlier guards have already moved to.
+    fn simulate_guards(&self, guards: &[State], collisions: Collisions) -> Vec<GuardReport> {
+        let n = guards.len();
+        let mut states: Vec<State> = guards.to_vec();
+        let mut outcomes: Vec<Option<Outcome>> = vec![None; n];
+        let mut visited: Vec<HashSet<(i32, i32)>> = guards.iter().map(|(pos, _)| HashSet::from([*pos])).collect();
+        let mut steps: Vec<usize> = vec![0; n];
+
+        // If all the guards still walking are ever in the same states as before, they'll loop forever.
+        // Guards that walk through each other never affect each other though, so then each guard just
+        // watches its own states (waiting for all the loops to line up could take ages!)
+        let mut seen: HashSet<Vec<Option<State>>> = HashSet::new();
+        let mut seen_by_guard: Vec<HashSet<State>> = vec![HashSet::new(); n];
+        loop {
+            let walking: Vec<Option<State>> = (0..n).map(|i| if outcomes[i].is_none() { Some(states[i]) } else { None }).collect();
+            if walking.iter().all(|s| s.is_none()) {
+                break
+            }
+            if collisions != Collisions::Ignore && !seen.insert(walking) {
+                for outcome in outcomes.iter_mut().filter(|o| o.is_none()) {
+                    *outcome = Some(Outcome::Looped);
+                }
+                break
+            }
+
+            for i in 0..n {
+                if outcomes[i].is_some() {
+                    continue
+                }
+                if collisions == Collisions::Ignore && !seen_by_guard[i].insert(states[i]) {
+                    outcomes[i] = Some(Outcome::Looped);
+                    continue
+                }
+                let (pos, direction) = states[i];
+                let Some((next_pos, next_direction)) = self.step(pos, direction, None) else {
+                    outcomes[i] = Some(Outcome::Exited(states[i]));
+                    continue
+                };
+
+                // Guards that left the map are out of the way, but stopped guards are still standing there!
+                let other_guard = (0..n).find(|&j| {
+                    j != i && states[j].0 == next_pos && !matches!(outcomes[j], Some(Outcome::Exited(_)))
+                });
+                match (other_guard, collisions) {
+                    (Some(_), Collisions::Block) if next_pos != pos => {
+                        states[i] = (pos, self.turn(direction));
+                        steps[i] += 1;
+                    }
+                    (Some(j), Collisions::Stop) if next_pos != pos => {
+                        outcomes[i] = Some(Outcome::Collided(j));
+                        if outcomes[j].is_none() {
+                            outcomes[j] = Some(Outcome::Collided(i));
+                        }
+                    }
+                    _ => {
+                        states[i] = (next_pos, next_direction);
+                        visited[i].insert(next_pos);
+                        steps[i] += 1;
+                    }
+                }
+            }
+        }
+
+        visited.into_iter().enumerate().map(|(i, walked)| GuardReport {
+            start: guards[i],
+            walked,
+            steps: steps[i],
+            outcome: outcomes[i].expect("Every guard should be done!")
+        }).collect()
+    }
+}
+
+fn part1(collisions: Collisions) {
+    let input = read_input("input.txt");
+    let (mut map, guards) = Grid::from_input(&input);
+    assert!(!guards.is_empty(), "There's no guard on the map!");
+
+    if guards.len() > 1 {
+        // More than one guard, so they all have to walk at the same time
+        let reports = map.simulate_guards(&guards, collisions);
+        let mut all_walked: HashSet<(i32, i32)> = HashSet::new();
+        for (i, report) in reports.iter().enumerate() {
+            let ((x, y), direction) = report.start;
+            let ending = match report.outcome {
+                Outcome::Exited(((exit_x, exit_y), exit_direction)) => {
+                    format!("left the map at ({exit_x}, {exit_y}) facing {}", direction_char(exit_direction))
+                }
+                Outcome::Collided(j) => format!("stopped after running into guard {j}"),
+                Outcome::Looped => String::from("is stuck in a loop")
+            };
+            println!("Guard {i} (started at ({x}, {y}) facing {}): visited {} cells, {ending} after {} steps",
+                direction_char(direction), report.walked.len(), report.steps);
+            all_walked.extend(report.walked.iter());
+        }
+        println!("{}", all_walked.len());
+        return
+    }
+
+    let (mut guard_position, mut guard_direction) = guards[0];
+    // walk_until_object can't tell when the guard goes round in circles, so it would walk forever
+    if map.guard_path(guard_position, guard_direction).is_none() {
+        println!("The guard is stuck in a loop, so they never leave!");
+        return
+    }
 
     // Have the little guy walk around :)
     // let mut counter = 0;
@@ -166,82 +410,212 @@ fn part1() {
 }
 
 fn part2() {
-    // This is so much repeating bad code!
+    // Brute forcing every cell worked but it took forever. Now we only try cells the guard actually walks on,
+    // and each trial picks up right before the guard would bump into the new obstruction.
     let input = read_input("input.txt");
-    
-    // Let's see if brute forcing works!
-    let mut loop_positions: Vec<(i32, i32)> = Vec::new();
-    for new_obs_x in 0..input.len() {
-        println!("Row {}/{}", new_obs_x, input.len());
-        for new_obs_y in 0..input[0].len() {
-
-            // Make these i32 so we don't have to do it individaully!
-            let new_obs_x: i32 = new_obs_x as i32;
-            let new_obs_y: i32 = new_obs_y as i32;
-
-            // println!("Row: {}, Col: {}", new_obs_x, new_obs_y);
-
-            // Make a new grid again :)
-            let mut map = Grid {
-                width: input.len() as i32,
-                height: input[0].len() as i32,
-                objects: Vec::new(),
-                walked: Vec::new(),
-                done: false
-            };
-    
-            let mut guard_position: (i32, i32) = (0, 0); // So the compiler doesn't yell at me for an uninitialized var
-            let mut guard_direction: (i32, i32) = (0, -1); // Starts facing up
-    
-            // Use this to figure out if we're stuck in a loop (we've been in this position and direction before!)
-            let mut previous_pos_dir: Vec<((i32, i32), (i32, i32))> = Vec::new();
-    
-            // Populate the map with "#" as objects
-            for i in 0..map.width {
-                for j in 0..map.height {
-                    if input[i as usize][j as usize] == '#' { // Use '' for char, "" for string!
-                        map.objects.push((i, j));
-                    }
-                    // Might as well look for the guard "^" while we're looping :)
-                    if input[i as usize][j as usize] == '^' {
-                        guard_position = (i, j);
-                    }
+    let (map, guards) = Grid::from_input(&input);
+    // The puzzle only ever has one guard, so with more we just look at the first one
+    let (guard_position, guard_direction) = guards[0];
+    match map.find_loop_positions(guard_position, guard_direction) {
+        Some(loop_positions) => {
+            if std::env::args().any(|arg| arg == "--list") {
+                for position in loop_positions.iter() {
+                    println!("{position:?}");
                 }
             }
+            println!("{}", loop_positions.len());
+        }
+        None => println!("The guard is already stuck in a loop, so there's nowhere new to trap them")
+    }
+}
 
-            // Try to add a new obstruction here. BUT IF THERE'S ALREADY ONE, SKIP :)
-            if map.objects.contains(&(new_obs_x, new_obs_y)) {
-                continue
-            } else {
-                map.objects.push((new_obs_x, new_obs_y));
+fn main() {
+    part1(Collisions::from_args());
+    part2();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Symmetry check: rotating or mirroring a map shouldn't change anything except where things end up.
+    // Lots of random non-square maps catch anywhere width and height get mixed up.
+
+    #[derive(Clone, Copy, Debug)]
+    enum Symmetry {
+        Rotate(usize), // Number of quarter turns clockwise
+        Transpose      // Mirror across the main diagonal, so the guard has to turn left instead!
+    }
+
+    impl Symmetry {
+        fn apply_to_map(self, input: &[Vec<char>]) -> Vec<Vec<char>> {
+            match self {
+                Symmetry::Rotate(turns) => {
+                    let mut output = input.to_vec();
+                    for _ in 0..turns {
+                        let height = output.len();
+                        let width = output[0].len();
+                        let rotate_guard = |c: char| match c { '^' => '>', '>' => 'v', 'v' => '<', '<' => '^', _ => c };
+                        output = (0..width).map(|y| (0..height).map(|x| rotate_guard(output[height - 1 - x][y])).collect()).collect();
+                    }
+                    output
+                }
+                Symmetry::Transpose => {
+                    let transpose_guard = |c: char| match c { '^' => '<', '<' => '^', '>' => 'v', 'v' => '>', _ => c };
+                    (0..input[0].len()).map(|y| (0..input.len()).map(|x| transpose_guard(input[x][y])).collect()).collect()
+                }
             }
+        }
 
-            // Also skip if this is the guard's initial position... (we're not allowed to...)
-            // I failed the first time bc of this!
-            if (new_obs_x, new_obs_y) == guard_position {
-                continue
+        /// Where (x, y) on the original map (of this width and height) ends up
+        fn apply_to_pos(self, pos: (i32, i32), width: i32, height: i32) -> (i32, i32) {
+            match self {
+                Symmetry::Rotate(turns) => {
+                    let (mut pos, mut width, mut height) = (pos, width, height);
+                    for _ in 0..turns {
+                        pos = (height - 1 - pos.1, pos.0);
+                        (width, height) = (height, width);
+                    }
+                    pos
+                }
+                Symmetry::Transpose => (pos.1, pos.0)
             }
-    
-            // Have the little guy walk around :) but this time with loop detection!
+        }
+    }
+
+    /// Everything about a single guard's walk that should survive a symmetry
+    #[derive(Debug, PartialEq)]
+    struct WalkSummary {
+        walked: HashSet<(i32, i32)>,
+        exit: Option<(i32, i32)>, // None if the guard never leaves
+        loop_positions: HashSet<(i32, i32)>
+    }
+
+    fn summarize_walk(input: &[Vec<char>]) -> WalkSummary {
+        let (mut map, guards) = Grid::from_input(input);
+        let report = map.simulate_guards(&guards, Collisions::Ignore).remove(0);
+        let exit = match report.outcome {
+            Outcome::Exited((pos, _)) => Some(pos),
+            _ => None
+        };
+        let mut loop_positions: HashSet<(i32, i32)> = HashSet::new();
+        if exit.is_some() {
+            // Part 1's jumping walk and the step-by-step one had better agree too
+            let (mut guard_position, mut guard_direction) = guards[0];
             while !map.done {
                 (guard_position, guard_direction) = map.walk_until_object(guard_position, guard_direction);
+            }
+            let unique_walked: HashSet<(i32, i32)> = map.walked.iter().cloned().collect();
+            assert_eq!(unique_walked, report.walked, "walk_until_object disagrees with step on\n{}", map_to_string(input));
+            loop_positions = map.find_loop_positions(guards[0].0, guards[0].1).expect("The guard left, so it can't be looping!").into_iter().collect();
+        }
+        WalkSummary { walked: report.walked, exit, loop_positions }
+    }
 
-                if previous_pos_dir.contains(&(guard_position, guard_direction)) {
-                    loop_positions.push((new_obs_x, new_obs_y));
-                    map.done = true;
-                    // println!("Obstruction found at ({}, {})", new_obs_x, new_obs_y);
+    /// Grid::step for a guard that turns left, which is what a real guard looks like in a mirror
+    fn step_left(map: &Grid, pos: (i32, i32), direction: (i32, i32), extra_object: Option<(i32, i32)>) -> Option<State> {
+        let next_pos = (pos.0 + direction.0, pos.1 + direction.1);
+        if !map.in_bounds(next_pos) {
+            return None
+        }
+        if map.is_blocked(next_pos, extra_object) {
+            return Some((pos, (direction.1, -direction.0)))
+        }
+        Some((next_pos, direction))
+    }
+
+    /// Cells a left-turning guard walks on and where they leave (no loop positions yet)
+    fn walk_left(map: &Grid, start: State, extra_object: Option<(i32, i32)>) -> WalkSummary {
+        let mut walked: HashSet<(i32, i32)> = HashSet::from([start.0]);
+        let mut seen: HashSet<State> = HashSet::new();
+        let mut state = start;
+        while seen.insert(state) {
+            match step_left(map, state.0, state.1, extra_object) {
+                Some(next_state) => {
+                    walked.insert(next_state.0);
+                    state = next_state;
                 }
+                None => return WalkSummary { walked, exit: Some(state.0), loop_positions: HashSet::new() }
+            }
+        }
+        WalkSummary { walked, exit: None, loop_positions: HashSet::new() }
+    }
 
-                // This won't add the initial guard position / direction, but that's what we want.
-                previous_pos_dir.push((guard_position, guard_direction));
-    
+    /// summarize_walk for a mirrored map, just brute forcing every obstruction since it's only a test
+    fn summarize_mirrored_walk(input: &[Vec<char>]) -> WalkSummary {
+        let (map, guards) = Grid::from_input(input);
+        let mut summary = walk_left(&map, guards[0], None);
+        if summary.exit.is_some() {
+            for x in 0..map.width {
+                for y in 0..map.height {
+                    let obstruction = (x, y);
+                    if obstruction != guards[0].0 && !map.objects.contains(&obstruction) && walk_left(&map, guards[0], Some(obstruction)).exit.is_none() {
+                        summary.loop_positions.insert(obstruction);
+                    }
+                }
             }
         }
+        summary
     }
-    println!("{}", loop_positions.len());
-}
 
-fn main() {
-    part1();
-    part2();
-}
\ No newline at end of file
+    fn map_to_string(input: &[Vec<char>]) -> String {
+        input.iter().map(|row| row.iter().collect::<String>()).collect::<Vec<String>>().join("\n")
+    }
+
+    /// Random rectangular map with exactly one guard. Just a little xorshift so we don't need a crate for it.
+    fn random_map(seed: &mut u64) -> Vec<Vec<char>> {
+        let mut next = |n: u64| -> usize {
+            *seed ^= *seed << 13;
+            *seed ^= *seed >> 7;
+            *seed ^= *seed << 17;
+            (*seed % n) as usize
+        };
+        let width = 1 + next(12);
+        let height = 1 + next(12);
+        let mut input: Vec<Vec<char>> = (0..height).map(|_| (0..width).map(|_| if next(6) == 0 { '#' } else { '.' }).collect()).collect();
+        let guard = ['^', '>', 'v', '<'][next(4)];
+        input[next(height as u64)][next(width as u64)] = guard;
+        input
+    }
+
+    #[test]
+    fn symmetries_only_move_things() {
+        let mut maps: Vec<Vec<Vec<char>>> = Vec::new();
+        if fs::metadata("input.txt").is_ok() {
+            maps.push(read_input("input.txt"));
+        }
+        let mut seed: u64 = 2024;
+        for _ in 0..500 {
+            maps.push(random_map(&mut seed));
+        }
+
+        let symmetries = [Symmetry::Rotate(1), Symmetry::Rotate(2), Symmetry::Rotate(3), Symmetry::Transpose];
+        let mut checked = 0;
+        for input in maps.iter() {
+            if Grid::from_input(input).1.len() != 1 {
+                continue // Only makes sense with one guard (reading order changes when you rotate)
+            }
+            let (width, height) = (input[0].len() as i32, input.len() as i32);
+            let original = summarize_walk(input);
+            for symmetry in symmetries {
+                let moved = |cells: &HashSet<(i32, i32)>| -> HashSet<(i32, i32)> {
+                    cells.iter().map(|&pos| symmetry.apply_to_pos(pos, width, height)).collect()
+                };
+                let expected = WalkSummary {
+                    walked: moved(&original.walked),
+                    exit: original.exit.map(|pos| symmetry.apply_to_pos(pos, width, height)),
+                    loop_positions: moved(&original.loop_positions)
+                };
+                let transformed = symmetry.apply_to_map(input);
+                let actual = match symmetry {
+                    Symmetry::Transpose => summarize_mirrored_walk(&transformed),
+                    Symmetry::Rotate(_) => summarize_walk(&transformed)
+                };
+                assert_eq!(expected, actual, "{:?} broke things! Original map:\n{}\nTransformed:\n{}",
+                    symmetry, map_to_string(input), map_to_string(&transformed));
+            }
+            checked += 1;
+        }
+        assert!(checked > 0, "No maps with exactly one guard to check!");
+    }
+}
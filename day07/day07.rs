@@ -1,114 +1,476 @@
 use std::fs;
 
-fn read_input(filename: &str) -> Vec<(i64, Vec<i64>)> {
+fn read_input(filename: &str) -> Vec<(i128, Vec<i128>)> {
     let mut input = Vec::new();
     let contents = fs::read_to_string(filename).expect("Can't read file!");
     for line in contents.lines() {
-        let line_vec: Vec<&str> = line.split(": ").collect();   
-        let total: i64 = line_vec[0].parse().unwrap();
-        let values: Vec<i64> = line_vec[1].split(" ").map(|x| x.parse::<i64>().unwrap()).collect();
+        let line_vec: Vec<&str> = line.split(": ").collect();
+        let total: i128 = line_vec[0].parse().unwrap();
+        let values: Vec<i128> = line_vec[1].split(" ").map(|x| x.parse::<i128>().unwrap()).collect();
         input.push((total,values))
     }
-    return input;
+    input
 }
 
-#[derive(Clone)]
-#[derive(PartialEq)]
-#[derive(Debug)]
-enum Operator {
-    Add,
-    Multiply,
-    Concatenate
-}
-
-// The itertools version was too confusing...
-fn cartesian_product<T: Clone>(set: Vec<T>, n: usize) -> Vec<Vec<T>> {
-    let mut result: Vec<Vec<T>> = vec![Vec::new()];
-    for _ in 0..n {
-        let mut new_result = Vec::new();
-        for item in &set {
-            for r in &result {
-                let mut new_r: Vec<T> = r.clone();
-                new_r.push(item.clone());
-                new_result.push(new_r);
+/// Why an operator couldn't give an answer
+#[derive(Debug, PartialEq)]
+enum Invalid {
+    Overflow,  // Too big even for an i128! We count these so we know what got skipped.
+    Undefined  // Doesn't make sense, like a negative power
+}
+
+/// Anything that can go between two numbers. Add new ones to `registry` so you can pick them from the command line.
+trait Operator {
+    fn name(&self) -> String; // What you type after --ops=
+    fn symbol(&self) -> String;
+    fn precedence(&self) -> u8; // Only used with --precedence. Higher binds tighter!
+    fn apply(&self, left: i128, right: i128) -> Result<i128, Invalid>;
+
+    /// Every `left` where `left op right == total`.
+    fn undo(&self, total: i128, right: i128) -> Result<Vec<i128>, Invalid>;
+
+    /// True if `left op right == total` for ANY left (like multiplying by 0), which undo can't list.
+    fn ignores_left(&self, _total: i128, _right: i128) -> bool {
+        false
+    }
+
+    /// If both sides are >= 0, is the answer too? Lets the solver throw out negative totals right away.
+    fn keeps_non_negative(&self) -> bool {
+        true
+    }
+}
+
+struct Add;
+struct Multiply;
+struct Subtract;
+struct Xor;
+struct Power;
+struct Concatenate {
+    base: u32
+}
+
+impl Operator for Add {
+    fn name(&self) -> String { String::from("add") }
+    fn symbol(&self) -> String { String::from("+") }
+    fn precedence(&self) -> u8 { 1 }
+    fn apply(&self, left: i128, right: i128) -> Result<i128, Invalid> {
+        left.checked_add(right).ok_or(Invalid::Overflow)
+    }
+    fn undo(&self, total: i128, right: i128) -> Result<Vec<i128>, Invalid> {
+        Ok(vec![total.checked_sub(right).ok_or(Invalid::Overflow)?])
+    }
+}
+
+impl Operator for Multiply {
+    fn name(&self) -> String { String::from("mul") }
+    fn symbol(&self) -> String { String::from("*") }
+    fn precedence(&self) -> u8 { 2 }
+    fn apply(&self, left: i128, right: i128) -> Result<i128, Invalid> {
+        left.checked_mul(right).ok_or(Invalid::Overflow)
+    }
+    fn undo(&self, total: i128, right: i128) -> Result<Vec<i128>, Invalid> {
+        if right != 0 && total % right == 0 {
+            Ok(vec![total.checked_div(right).ok_or(Invalid::Overflow)?])
+        } else {
+            Ok(Vec::new())
+        }
+    }
+    fn ignores_left(&self, total: i128, right: i128) -> bool {
+        right == 0 && total == 0
+    }
+}
+
+impl Operator for Subtract {
+    fn name(&self) -> String { String::from("sub") }
+    fn symbol(&self) -> String { String::from("-") }
+    fn precedence(&self) -> u8 { 1 }
+    fn apply(&self, left: i128, right: i128) -> Result<i128, Invalid> {
+        left.checked_sub(right).ok_or(Invalid::Overflow)
+    }
+    fn undo(&self, total: i128, right: i128) -> Result<Vec<i128>, Invalid> {
+        Ok(vec![total.checked_add(right).ok_or(Invalid::Overflow)?])
+    }
+    fn keeps_non_negative(&self) -> bool {
+        false
+    }
+}
+
+impl Operator for Xor {
+    fn name(&self) -> String { String::from("xor") }
+    fn symbol(&self) -> String { String::from("^") }
+    fn precedence(&self) -> u8 { 0 } // Lowest, like in C
+    fn apply(&self, left: i128, right: i128) -> Result<i128, Invalid> {
+        Ok(left ^ right)
+    }
+    fn undo(&self, total: i128, right: i128) -> Result<Vec<i128>, Invalid> {
+        Ok(vec![total ^ right])
+    }
+}
+
+impl Operator for Power {
+    fn name(&self) -> String { String::from("pow") }
+    fn symbol(&self) -> String { String::from("**") }
+    fn precedence(&self) -> u8 { 3 }
+    fn apply(&self, left: i128, right: i128) -> Result<i128, Invalid> {
+        if right < 0 {
+            return Err(Invalid::Undefined)
+        }
+        match (u32::try_from(right), left) {
+            (Ok(exponent), _) => left.checked_pow(exponent).ok_or(Invalid::Overflow),
+            // Huge exponents only work for 0, 1, and -1
+            (Err(_), 0 | 1) => Ok(left),
+            (Err(_), -1) => Ok(if right % 2 == 0 { 1 } else { -1 }),
+            (Err(_), _) => Err(Invalid::Overflow)
+        }
+    }
+    fn undo(&self, total: i128, right: i128) -> Result<Vec<i128>, Invalid> {
+        if right <= 0 {
+            return Ok(Vec::new()) // right == 0 is handled by ignores_left
+        }
+        let Ok(exponent) = u32::try_from(right) else {
+            return Ok([0, 1, -1].into_iter().filter(|&left| self.apply(left, right) == Ok(total)).collect())
+        };
+        let root = integer_root(total.unsigned_abs(), exponent);
+        // root is one too big for an i128 when total is i128::MIN (and right is 1), but -root still fits!
+        let mut lefts: Vec<i128> = Vec::new();
+        for left in [i128::try_from(root).ok(), 0_i128.checked_sub_unsigned(root)].into_iter().flatten() {
+            if self.apply(left, right) == Ok(total) && !lefts.contains(&left) {
+                lefts.push(left);
             }
         }
-        result = new_result;
+        Ok(lefts)
+    }
+    fn ignores_left(&self, total: i128, right: i128) -> bool {
+        right == 0 && total == 1
     }
-    return result
 }
 
-fn evaluate(values: Vec<i64>, operators: Vec<Operator>) -> i64 {
+/// Biggest r with r^exponent <= n. Binary search so there's no float rounding to worry about.
+fn integer_root(n: u128, exponent: u32) -> u128 {
+    let (mut low, mut high) = (0_u128, n);
+    while low < high {
+        let mid = low + (high - low).div_ceil(2);
+        match mid.checked_pow(exponent) {
+            Some(power) if power <= n => low = mid,
+            _ => high = mid - 1
+        }
+    }
+    low
+}
+
+impl Operator for Concatenate {
+    fn name(&self) -> String {
+        if self.base == 10 { String::from("concat") } else { format!("concat{}", self.base) }
+    }
+    fn symbol(&self) -> String {
+        if self.base == 10 { String::from("||") } else { format!("||{}", self.base) }
+    }
+    fn precedence(&self) -> u8 { 4 } // Gluing digits together happens before anything else
+    fn apply(&self, left: i128, right: i128) -> Result<i128, Invalid> {
+        if left < 0 || right < 0 {
+            return Err(Invalid::Undefined) // No minus signs in the middle of numbers
+        }
+        if left == 0 {
+            return Ok(right) // Leading zeros don't count (and this way the shift can't overflow)
+        }
+        let shift = self.shift(right).ok_or(Invalid::Overflow)?;
+        left.checked_mul(shift).and_then(|shifted| shifted.checked_add(right)).ok_or(Invalid::Overflow)
+    }
+    fn undo(&self, total: i128, right: i128) -> Result<Vec<i128>, Invalid> {
+        // total has to end with the digits of right
+        if right < 0 || total < right {
+            return Ok(Vec::new())
+        }
+        match self.shift(right) {
+            Some(shift) if (total - right) % shift == 0 => Ok(vec![(total - right) / shift]),
+            None if total == right => Ok(vec![0]), // right is so long only 0 can go in front
+            _ => Ok(Vec::new())
+        }
+    }
+}
+
+impl Concatenate {
+    /// What the left side gets multiplied by to make room for right's digits. No strings needed!
+    fn shift(&self, right: i128) -> Option<i128> {
+        let digits = if right == 0 { 1 } else { right.unsigned_abs().ilog(self.base as u128) + 1 };
+        (self.base as i128).checked_pow(digits)
+    }
+}
+
+/// All the operators you can pick with --ops=. Concatenation also works in any base from 2 to 36 (e.g. concat2).
+fn registry() -> Vec<Box<dyn Operator>> {
+    vec![
+        Box::new(Add),
+        Box::new(Multiply),
+        Box::new(Concatenate { base: 10 }),
+        Box::new(Subtract),
+        Box::new(Xor),
+        Box::new(Power),
+    ]
+}
+
+fn operator_from_name(name: &str) -> Option<Box<dyn Operator>> {
+    if let Some(op) = registry().into_iter().find(|op| op.name() == name || op.symbol() == name) {
+        return Some(op)
+    }
+    let base: u32 = name.strip_prefix("concat")?.parse().ok()?;
+    if (2..=36).contains(&base) { Some(Box::new(Concatenate { base })) } else { None }
+}
+
+/// Left to right like the puzzle says, or normal maths order with `precedence` (operators on the same level go left to right)
+fn evaluate(values: &[i128], operators: &[&dyn Operator], precedence: bool) -> Result<i128, Invalid> {
     assert!(values.len() - operators.len() == 1);
-    let mut total = values[0];
+    let mut stack = Stack::new(values[0], precedence);
     for (i, op) in operators.iter().enumerate() {
-        if *op == Operator::Add {
-            total += values[i+1];
-        }
-        if *op == Operator::Multiply {
-            total *= values[i+1];
+        stack = stack.push(*op, values[i+1])?;
+    }
+    stack.finish()
+}
+
+fn format_equation(total: i128, values: &[i128], operators: &[&dyn Operator]) -> String {
+    let mut equation = format!("{total} = {}", values[0]);
+    for (op, value) in operators.iter().zip(values[1..].iter()) {
+        equation += &format!(" {} {value}", op.symbol());
+    }
+    equation
+}
+
+/// Shunting yard, so we can evaluate with precedence one operator at a time.
+/// Without precedence everything's on the same level, which is just left to right.
+#[derive(Clone)]
+struct Stack<'a> {
+    values: Vec<i128>,
+    operators: Vec<&'a dyn Operator>,
+    precedence: bool
+}
+
+impl<'a> Stack<'a> {
+    fn new(first_value: i128, precedence: bool) -> Stack<'a> {
+        Stack { values: vec![first_value], operators: Vec::new(), precedence }
+    }
+
+    fn reduce_once(&mut self) -> Result<(), Invalid> {
+        let op = self.operators.pop().expect("Nothing to reduce!");
+        let right = self.values.pop().expect("Stack ran out of values!");
+        let left = self.values.pop().expect("Stack ran out of values!");
+        self.values.push(op.apply(left, right)?);
+        Ok(())
+    }
+
+    fn push(mut self, op: &'a dyn Operator, value: i128) -> Result<Stack<'a>, Invalid> {
+        while self.operators.last().is_some_and(|top| !self.precedence || top.precedence() >= op.precedence()) {
+            self.reduce_once()?;
         }
-        if *op == Operator::Concatenate {
-            let temp_total = total.to_string();
-            let temp_value = values[i+1].to_string();
-            total = format!("{}{}", temp_total, temp_value).parse().expect("Something went wrong when concatenating!");
+        self.operators.push(op);
+        self.values.push(value);
+        Ok(self)
+    }
+
+    fn finish(mut self) -> Result<i128, Invalid> {
+        while !self.operators.is_empty() {
+            self.reduce_once()?;
         }
+        Ok(self.values.pop().expect("Stack ran out of values!"))
     }
-    return total
 }
 
-fn part1(input: &Vec<(i64, Vec<i64>)> ) {
-    let mut answer = 0;
-    for (i, eq) in input.iter().enumerate() {
-        let total = eq.0.clone();
-        let values = eq.1.clone();
-
-        // Quick heuristic with the bounds of the calculation
-        let min = evaluate(values.clone(), vec![Operator::Add; values.len() - 1]);
-        let max = evaluate(values.clone(), vec![Operator::Multiply; values.len() - 1]);
-        if (min > total) && (max < total) {
-            continue
-        }
-        
-        // But beyond this, I'll just brute force?
-        // I truly think that there's a way to do this calculation iteratively 
-        // and guarantee that the total slowly increases. But I'll have to think more :)
-        let operators = vec![Operator::Add, Operator::Multiply];
-        let op_combinations = cartesian_product(operators, values.len() - 1);
-        for op in op_combinations.iter() {
-            if total == evaluate(values.clone(), op.to_vec()) {
-                answer += total;
-                break
+/// How a search went. Counts are u128 since zeros and ones can make the number of solutions explode.
+#[derive(Debug, Default)]
+struct Tally {
+    solutions: u128,
+    overflowed: u128 // Branches we had to give up on because the numbers got too big
+}
+
+/// Walks through operator assignments for one equation. `visit` gets every solution (operators left to right)
+/// and returns true to stop looking, so the same search finds the first solution or all of them.
+struct Search<'a, F: FnMut(&[&'a dyn Operator]) -> bool> {
+    operators: &'a [Box<dyn Operator>],
+    non_negative: bool,
+    prefix: Vec<&'a dyn Operator>, // Chosen going forwards
+    suffix: Vec<&'a dyn Operator>, // Chosen going backwards, so it's in reverse!
+    tally: Tally,
+    visit: F
+}
+
+impl<'a, F: FnMut(&[&'a dyn Operator]) -> bool> Search<'a, F> {
+    fn new(operators: &'a [Box<dyn Operator>], values: &[i128], visit: F) -> Search<'a, F> {
+        let non_negative = operators.iter().all(|op| op.keeps_non_negative()) && values.iter().all(|&v| v >= 0);
+        Search { operators, non_negative, prefix: Vec::new(), suffix: Vec::new(), tally: Tally::default(), visit }
+    }
+
+    fn found(&mut self) -> bool {
+        self.tally.solutions += 1;
+        let ops: Vec<&'a dyn Operator> = self.prefix.iter().chain(self.suffix.iter().rev()).cloned().collect();
+        (self.visit)(&ops)
+    }
+
+    /// Works right to left: undo the last operator to get what everything before it must add up to, and so on.
+    fn backward(&mut self, total: i128, values: &[i128]) -> bool {
+        let (&last, rest) = values.split_last().expect("No values!");
+        if rest.is_empty() {
+            return total == last && self.found()
+        }
+        let operators = self.operators;
+        for op in operators.iter() {
+            let op = op.as_ref();
+            if op.ignores_left(total, last) {
+                // Anything at all works for the rest, as long as it doesn't blow up
+                self.suffix.push(op);
+                let stop = self.forward(Stack::new(rest[0], false), &rest[1..], None);
+                self.suffix.pop();
+                if stop {
+                    return true
+                }
+            }
+            let previous_totals = match op.undo(total, last) {
+                Ok(previous_totals) => previous_totals,
+                Err(_) => {
+                    self.tally.overflowed += 1;
+                    continue
+                }
+            };
+            for previous_total in previous_totals {
+                if self.non_negative && previous_total < 0 {
+                    continue
+                }
+                self.suffix.push(op);
+                let stop = self.backward(previous_total, rest);
+                self.suffix.pop();
+                if stop {
+                    return true
+                }
             }
         }
+        false
+    }
+
+    /// Tries everything left to right. Needed with precedence (undoing doesn't work once operators can jump
+    /// the queue), and for listing the "anything works" part of the backward search when `target` is None.
+    fn forward(&mut self, stack: Stack<'a>, values: &[i128], target: Option<i128>) -> bool {
+        let Some((&next, rest)) = values.split_first() else {
+            return match stack.finish() {
+                Ok(total) => target.is_none_or(|t| t == total) && self.found(),
+                Err(Invalid::Overflow) => {
+                    self.tally.overflowed += 1;
+                    false
+                }
+                Err(Invalid::Undefined) => false
+            }
+        };
+        let operators = self.operators;
+        for op in operators.iter() {
+            match stack.clone().push(op.as_ref(), next) {
+                Ok(new_stack) => {
+                    self.prefix.push(op.as_ref());
+                    let stop = self.forward(new_stack, rest, target);
+                    self.prefix.pop();
+                    if stop {
+                        return true
+                    }
+                }
+                Err(Invalid::Overflow) => self.tally.overflowed += 1,
+                Err(Invalid::Undefined) => ()
+            }
+        }
+        false
+    }
+
+    fn run(&mut self, total: i128, values: &[i128], precedence: bool) {
+        if precedence {
+            self.forward(Stack::new(values[0], true), &values[1..], Some(total));
+        } else {
+            self.backward(total, values);
+        }
     }
-    println!("{}", answer); // It's surprisingly fast! I forget how good computers are at arithmetic sometimes.
 }
 
+/// The operators (left to right) that make the equation true, if there are any
+fn solve<'a>(total: i128, values: &[i128], operators: &'a [Box<dyn Operator>], precedence: bool) -> Option<Vec<&'a dyn Operator>> {
+    let mut solution: Option<Vec<&'a dyn Operator>> = None;
+    let mut search = Search::new(operators, values, |ops: &[&'a dyn Operator]| {
+        solution = Some(ops.to_vec());
+        true
+    });
+    search.run(total, values, precedence);
+    solution
+}
 
-fn part2(input: &Vec<(i64, Vec<i64>)> ) {
+fn calibration_result(input: &[(i128, Vec<i128>)], operators: &[Box<dyn Operator>], precedence: bool) -> i128 {
     let mut answer = 0;
-    for (i, eq) in input.iter().enumerate() {
-        println!("{}/{}", i+1, input.len());
-        let total = eq.0.clone();
-        let values = eq.1.clone();
-
-        // The heuristic no longer works here!
-        
-        let operators = vec![Operator::Add, Operator::Multiply, Operator::Concatenate];
-        let op_combinations = cartesian_product(operators, values.len() - 1);
-        for op in op_combinations.iter() {
-            if total == evaluate(values.clone(), op.to_vec()) {
-                answer += total;
-                break
+    for (total, values) in input.iter() {
+        if let Some(ops) = solve(*total, values, operators, precedence) {
+            debug_assert_eq!(evaluate(values, &ops, precedence), Ok(*total));
+            answer += total;
+        }
+    }
+    answer
+}
+
+/// Every way to solve every equation instead of stopping at the first one. `list` prints each of them too.
+fn count_all_solutions(input: &[(i128, Vec<i128>)], operators: &[Box<dyn Operator>], precedence: bool, list: bool) {
+    let mut answer: i128 = 0;
+    let mut all_solutions: u128 = 0;
+    let mut all_overflowed: u128 = 0;
+    for (total, values) in input.iter() {
+        let mut search = Search::new(operators, values, |ops: &[&dyn Operator]| {
+            if list {
+                println!("  {}", format_equation(*total, values, ops));
             }
+            false // Keep going!
+        });
+        search.run(*total, values, precedence);
+        let tally = search.tally;
+        println!("{total}: {} solutions ({} branches pruned for overflowing)", tally.solutions, tally.overflowed);
+        if tally.solutions > 0 {
+            answer += total;
         }
+        all_solutions += tally.solutions;
+        all_overflowed += tally.overflowed;
     }
+    println!("{all_solutions} solutions in total, {all_overflowed} branches pruned for overflowing");
     println!("{}", answer);
 }
 
+fn part1(input: &[(i128, Vec<i128>)]) {
+    // I used to brute force every combination of operators here. Going backwards is WAY faster :)
+    let operators: Vec<Box<dyn Operator>> = vec![Box::new(Add), Box::new(Multiply)];
+    println!("{}", calibration_result(input, &operators, false));
+}
+
+fn part2(input: &[(i128, Vec<i128>)]) {
+    let operators: Vec<Box<dyn Operator>> = vec![Box::new(Add), Box::new(Multiply), Box::new(Concatenate { base: 10 })];
+    println!("{}", calibration_result(input, &operators, false));
+}
+
+/// Puzzle variants, e.g. `cargo run -- --ops=add,mul,xor --precedence`
+/// Add --count to count every solution instead of finding just one, or --list to print them all.
+fn variant(input: &[(i128, Vec<i128>)], op_names: &str, precedence: bool, count: bool, list: bool) {
+    let operators: Vec<Box<dyn Operator>> = op_names
+        .split(',')
+        .map(|name| operator_from_name(name).unwrap_or_else(|| {
+            let known: Vec<String> = registry().iter().map(|op| op.name()).collect();
+            panic!("Unknown operator {name}! Try one of {} (or concat2 to concat36)", known.join(", "))
+        }))
+        .collect();
+    let symbols: Vec<String> = operators.iter().map(|op| op.symbol()).collect();
+    println!("Operators {} ({}):", symbols.join(" "), if precedence { "with precedence" } else { "left to right" });
+    if count || list {
+        count_all_solutions(input, &operators, precedence, list);
+    } else {
+        println!("{}", calibration_result(input, &operators, precedence));
+    }
+}
+
 fn main() {
     let input = read_input("input.txt");
+    let precedence = std::env::args().any(|arg| arg == "--precedence");
+    let count = std::env::args().any(|arg| arg == "--count");
+    let list = std::env::args().any(|arg| arg == "--list");
+    let op_names = std::env::args().find_map(|arg| arg.strip_prefix("--ops=").map(|s| s.to_string()));
+    if op_names.is_some() || precedence || count || list {
+        variant(&input, op_names.as_deref().unwrap_or("add,mul,concat"), precedence, count, list);
+        return
+    }
     part1(&input);
     part2(&input);
-}
\ No newline at end of file
+}
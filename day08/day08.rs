@@ -1,11 +1,10 @@
 use std::fs;
-use std::collections::HashSet;
-use itertools::iproduct;
+use std::collections::BTreeSet;
+use itertools::Itertools;
 use num::integer::gcd;
-use std::cmp;
 
-// use std::fs::File;
-// use std::io::{self, BufWriter, Write};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
 
 fn read_input(filename: &str) -> Vec<Vec<char>> {
     let mut output: Vec<Vec<char>> = Vec::new();
@@ -14,143 +13,276 @@ fn read_input(filename: &str) -> Vec<Vec<char>> {
         let char_array = line.chars().collect();
         output.push(char_array);
     }
-    return output
+    output
 }
 
 /// I'm adapting the Grid from day 6. I should make it more generic :)
 struct Grid {
     width: i32,
     height: i32,
-    antennas: Vec<(char, i32, i32)>,
-    antinodes: Vec<(i32, i32)>
+    antennas: Vec<(char, i32, i32)>
 }
 
 impl Grid {
     fn find_antennas(&self, freq: char) -> Vec<(char, i32, i32)> {
-        return self.antennas.iter().filter(|(f, _, _)| freq == *f).cloned().collect();
+        self.antennas.iter().filter(|(f, _, _)| freq == *f).cloned().collect()
+    }
+}
+
+/// One bit per cell, so unions and overlaps are just bitwise ops
+#[derive(Clone)]
+struct BitGrid {
+    width: i32,
+    height: i32,
+    bits: Vec<u64>
+}
+
+impl BitGrid {
+    fn new(width: i32, height: i32) -> BitGrid {
+        let cells = (width * height) as usize;
+        BitGrid { width, height, bits: vec![0; cells.div_ceil(64)] }
+    }
+
+    fn index(&self, coords: (i32, i32)) -> usize {
+        // (row, col) like everywhere else in here
+        assert!(coords.0 >= 0 && coords.0 < self.height && coords.1 >= 0 && coords.1 < self.width, "{:?} is off the map!", coords);
+        (coords.0 * self.width + coords.1) as usize
+    }
+
+    fn insert(&mut self, coords: (i32, i32)) {
+        let i = self.index(coords);
+        self.bits[i / 64] |= 1 << (i % 64);
+    }
+
+    fn contains(&self, coords: (i32, i32)) -> bool {
+        let i = self.index(coords);
+        self.bits[i / 64] & (1 << (i % 64)) != 0
+    }
+
+    fn count(&self) -> usize {
+        self.bits.iter().map(|word| word.count_ones() as usize).sum()
+    }
+
+    fn union_with(&mut self, other: &BitGrid) {
+        for (word, other_word) in self.bits.iter_mut().zip(other.bits.iter()) {
+            *word |= other_word;
+        }
+    }
+
+    fn intersection(&self, other: &BitGrid) -> BitGrid {
+        let bits = self.bits.iter().zip(other.bits.iter()).map(|(a, b)| a & b).collect();
+        BitGrid { width: self.width, height: self.height, bits }
+    }
+
+    fn cells(&self) -> impl Iterator<Item = (i32, i32)> + '_ {
+        (0..self.height).cartesian_product(0..self.width).filter(|&coords| self.contains(coords))
     }
 }
 
 impl Grid {
     fn in_bounds(&self, coords: (i32, i32)) -> bool {
         // First coord is row! So it's height not width!
-        return (coords.0 < self.height) && (coords.0 >= 0) && (coords.1 < self.width) && (coords.1 >= 0)
-    }
-}
-
-// impl Grid {
-//     fn write_to_txt(&self, fname: String) -> io::Result<()> {
-//         let file = File::create(fname)?;
-//         let mut writer = BufWriter::new(file);
-//         for row in 0..self.height {
-//             let mut line: Vec<char> = Vec::new();
-//             let antennas_y: Vec<i32> = self.antennas.iter().filter(|&(_, x, _)| x == &row).map(|&(_, _, y)| y).collect();
-//             let antinodes_y: Vec<i32> = self.antinodes.iter().filter(|&(x, _)| x == &row).map(|&(_, y)| y).collect();
-//             for col in 0..self.width {
-//                 if antennas_y.contains(&col) {
-//                     line.push('0');
-//                 } else if antinodes_y.contains(&col) {
-//                     line.push('#');
-//                 } else {
-//                     line.push('.');
-//                 }
-//             }
-//             // Write this line to the wrtier buffer
-//             writeln!(writer, "{}", line.into_iter().collect::<String>())?;
-//         }
-//         writer.flush()?;
-//         Ok(())
-//     }
-// }
-
-fn get_antinodes_part1(antenna1: (char, i32, i32), antenna2: (char, i32, i32)) -> [(i32, i32); 2] {
+        (coords.0 < self.height) && (coords.0 >= 0) && (coords.1 < self.width) && (coords.1 >= 0)
+    }
+}
+
+
+/// Which antinodes a pair of antennas makes. Harmonic k is the point k steps past each antenna (going away from
+/// the other one), so k = 0 is the antennas themselves. A step is the whole distance between the antennas, or with
+/// `every_grid_point` the smallest step that still lands on the grid (and then the points in between count too).
+#[derive(Clone, Copy, Debug)]
+struct Harmonics {
+    min: i32,
+    max: Option<i32>, // None means keep going until we're off the map
+    every_grid_point: bool
+}
+
+/// Part 1: just the point one distance past each antenna
+const FIRST_HARMONIC: Harmonics = Harmonics { min: 1, max: Some(1), every_grid_point: false };
+/// Part 2: every grid point on the line through both antennas
+const WHOLE_LINE: Harmonics = Harmonics { min: 0, max: None, every_grid_point: true };
+
+fn get_antinodes(map: &Grid, antenna1: (char, i32, i32), antenna2: (char, i32, i32), harmonics: Harmonics) -> Vec<(i32, i32)> {
     // I should have better error handling, but this works for now :)
-    assert!(antenna1.0 == antenna2.0); // Make sure they're the same frequency 
-    assert!((antenna1 != antenna2)); // Make sure this isn't the same antenna
-    // I'm visualizing this as antenna1 is to the upper left of antenna2, but this should be general...
-    let dx: i32 = antenna2.1 - antenna1.1;
-    let dy: i32 = antenna2.2 - antenna1.2;
-    let antinode1: (i32, i32) = (antenna1.1 - dx, antenna1.2 - dy);
-    let antinode2: (i32, i32) = (antenna2.1 + dx, antenna2.2 + dy);
-    return [antinode1, antinode2]
-}
-
-fn get_antinodes_part2(map: &Grid, antenna1: (char, i32, i32), antenna2: (char, i32, i32)) -> Vec<(i32, i32)> {
-    assert!(antenna1.0 == antenna2.0); // Make sure they're the same frequency 
-    assert!((antenna1 != antenna2)); // Make sure this isn't the same antenna
-    // We can do the same thing to start with but then simplify the slope.
+    assert!(antenna1.0 == antenna2.0); // Make sure they're the same frequency
+    assert!((antenna1.1, antenna1.2) != (antenna2.1, antenna2.2)); // Make sure this isn't the same antenna
     // x and y notation are backwards but consistent...
     let mut dx: i32 = antenna2.1 - antenna1.1;
     let mut dy: i32 = antenna2.2 - antenna1.2;
-    let this_gcd: i32 = gcd::<i32>(dx, dy);
-    dx = dx / this_gcd;
-    dy = dy / this_gcd;
-    // Now we can just start moving in both directions (from antenna1) but (TRY) to stay in bounds!
-    let max_steps: i32 = cmp::max((map.height / dx).abs(), (map.width / dy).abs()); // i32 truncation makes this easy!
-    // We'll definitely escape bounds, but we'll deal with that after!
+    let mut steps_between: i32 = 1; // How many steps it takes to get from antenna1 to antenna2
+    if harmonics.every_grid_point {
+        // Simplify the slope. gcd is never 0 here since the antennas are in different spots (and gcd(0, n) = n)
+        steps_between = gcd::<i32>(dx, dy);
+        dx /= steps_between;
+        dy /= steps_between;
+    }
+
     let mut antinodes: Vec<(i32, i32)> = Vec::new();
-    for step in 0..=max_steps { // Don't forget step 0! And the last step!
-        antinodes.push((antenna1.1 - (dx * step), antenna1.2 - (dy * step)));
-        antinodes.push((antenna1.1 + (dx * step), antenna1.2 + (dy * step)));
+    // Walk away from antenna1 backwards and away from antenna2 forwards until we leave the map (or run out of harmonics)
+    for (start, direction) in [((antenna1.1, antenna1.2), -1), ((antenna2.1, antenna2.2), 1)] {
+        let mut k = harmonics.min;
+        loop {
+            let antinode = (start.0 + direction * k * dx, start.1 + direction * k * dy);
+            if !map.in_bounds(antinode) || harmonics.max.is_some_and(|max| k > max) {
+                break
+            }
+            antinodes.push(antinode);
+            k += 1;
+        }
     }
-    return antinodes
+    // The points strictly between the antennas are on the line too, but they're not past either antenna
+    if harmonics.every_grid_point && harmonics.min <= 0 {
+        for step in 1..steps_between {
+            antinodes.push((antenna1.1 + step * dx, antenna1.2 + step * dy));
+        }
+    }
+    antinodes
 }
 
-fn part1(map: &mut Grid) {
-    let frequencies: HashSet<char> = map.antennas.iter().map(|(freq, _, _)| freq).cloned().collect();
-    for f in frequencies.into_iter() {
-        let antennas = &map.find_antennas(f);
-        for (a1, a2) in iproduct!(antennas, antennas) {
-            // We waste calculations on (a1, a2) vs (a2, a1) but it's okay :)
-            if a1 != a2 {
-                let antinodes = get_antinodes_part1(*a1, *a2);
-                for antinode in antinodes.into_iter() {
-                    if map.in_bounds(antinode) {
-                        map.antinodes.push(antinode);
-                    }
-                }
+struct AntinodeReport {
+    per_frequency: Vec<(char, BitGrid)>, // Sorted by frequency
+    all: BitGrid,
+    shared: BitGrid // Made by more than one frequency
+}
 
+fn find_antinodes(map: &Grid, harmonics: Harmonics) -> AntinodeReport {
+    let frequencies: BTreeSet<char> = map.antennas.iter().map(|(freq, _, _)| freq).cloned().collect();
+    let mut report = AntinodeReport {
+        per_frequency: Vec::new(),
+        all: BitGrid::new(map.width, map.height),
+        shared: BitGrid::new(map.width, map.height)
+    };
+    for f in frequencies.into_iter() {
+        let mut antinodes = BitGrid::new(map.width, map.height);
+        // Each pair only once this time! Antinodes don't care which antenna comes first
+        for (a1, a2) in map.find_antennas(f).into_iter().tuple_combinations() {
+            for antinode in get_antinodes(map, a1, a2, harmonics).into_iter() {
+                antinodes.insert(antinode);
             }
         }
+        // Anything we've already seen from another frequency is now shared
+        let overlap = report.all.intersection(&antinodes);
+        report.shared.union_with(&overlap);
+        report.all.union_with(&antinodes);
+        report.per_frequency.push((f, antinodes));
     }
-    let antinodes: HashSet<(i32, i32)> = map.antinodes.iter().cloned().collect();
-    println!("{}", antinodes.len());           
+    report
 }
 
-fn part2(map: &mut Grid) {
-    let frequencies: HashSet<char> = map.antennas.iter().map(|(freq, _, _)| freq).cloned().collect();
-    for f in frequencies.into_iter() {
-        let antennas = &map.find_antennas(f);
-        for (a1, a2) in iproduct!(antennas, antennas) {
-            // We waste calculations on (a1, a2) vs (a2, a1) but it's okay :)
-            if a1 != a2 {
-                let antinodes = get_antinodes_part2(map, *a1, *a2);
-                for antinode in antinodes.into_iter() {
-                    if map.in_bounds(antinode) {
-                        map.antinodes.push(antinode);
-                    }
-                }
+fn print_report(report: &AntinodeReport) {
+    for (f, antinodes) in report.per_frequency.iter() {
+        println!("Frequency {f}: {} antinodes", antinodes.count());
+    }
+    println!("{} cells have antinodes from more than one frequency", report.shared.count());
+    for coords in report.shared.cells() {
+        let frequencies: Vec<String> = report.per_frequency.iter().filter(|(_, antinodes)| antinodes.contains(coords)).map(|(f, _)| f.to_string()).collect();
+        println!("  {:?}: {}", coords, frequencies.join(", "));
+    }
+}
+
+// ANSI colours to cycle through for each frequency (bright versions after the normal ones)
+const COLOURS: [u8; 12] = [31, 32, 33, 34, 35, 36, 91, 92, 93, 94, 95, 96];
 
+impl Grid {
+    /// The map like in the puzzle examples: antennas keep their own character and antinodes are '#'.
+    /// With `colour`, everything gets its frequency's colour (shared antinodes are bold white) and
+    /// antennas with an antinode on top are highlighted.
+    fn render(&self, report: &AntinodeReport, colour: bool) -> String {
+        let mut cells: Vec<Vec<char>> = vec![vec!['.'; self.width as usize]; self.height as usize];
+        for &(f, row, col) in self.antennas.iter() {
+            cells[row as usize][col as usize] = f;
+        }
+        let colour_of = |f: char| -> u8 {
+            let i = report.per_frequency.iter().position(|(freq, _)| *freq == f).unwrap_or(0);
+            COLOURS[i % COLOURS.len()]
+        };
+
+        let mut output = String::new();
+        for row in 0..self.height {
+            for col in 0..self.width {
+                let c = cells[row as usize][col as usize];
+                let is_antinode = report.all.contains((row, col));
+                if !colour {
+                    output.push(if c == '.' && is_antinode { '#' } else { c });
+                } else if c != '.' {
+                    let highlight = if is_antinode { "7;" } else { "" }; // 7 is reverse video
+                    output += &format!("\x1b[{highlight}{}m{c}\x1b[0m", colour_of(c));
+                } else if report.shared.contains((row, col)) {
+                    output += "\x1b[1;97m#\x1b[0m";
+                } else if is_antinode {
+                    let (f, _) = report.per_frequency.iter().find(|(_, antinodes)| antinodes.contains((row, col))).unwrap();
+                    output += &format!("\x1b[{}m#\x1b[0m", colour_of(*f));
+                } else {
+                    output.push('.');
+                }
             }
+            output.push('\n');
         }
+        output
     }
-    let antinodes: HashSet<(i32, i32)> = map.antinodes.iter().cloned().collect();
-    println!("{}", antinodes.len());           
+
+    fn write_to_txt(&self, report: &AntinodeReport, fname: String) -> io::Result<()> {
+        let file = File::create(fname)?;
+        let mut writer = BufWriter::new(file);
+        write!(writer, "{}", self.render(report, false))?;
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+/// What to do with the antinodes besides counting them (all from the command line)
+struct Options {
+    report: bool, // --report: per-frequency counts and shared cells
+    render: bool, // --render: draw the map
+    colour: bool, // --colour: ...in colour
+    save: bool    // --save: write the map to antinodes_part1.txt, etc.
+}
+
+fn show(map: &Grid, report: &AntinodeReport, options: &Options, part: u8) {
+    if options.report {
+        print_report(report);
+    }
+    if options.render || options.colour {
+        print!("{}", map.render(report, options.colour));
+    }
+    if options.save {
+        if let Err(e) = map.write_to_txt(report, format!("antinodes_part{part}.txt")) {
+            eprintln!("Failed to write to file: {}", e);
+        }
+    }
+}
+
+fn part1(map: &Grid, options: &Options) {
+    let report = find_antinodes(map, FIRST_HARMONIC);
+    show(map, &report, options, 1);
+    println!("{}", report.all.count());
+}
+
+fn part2(map: &Grid, options: &Options) {
+    let report = find_antinodes(map, WHOLE_LINE);
+    show(map, &report, options, 2);
+    println!("{}", report.all.count());
 }
 
 fn main() {
     let input = read_input("input.txt");
+    let flag = |name: &str| std::env::args().any(|arg| arg == name);
+    let options = Options {
+        report: flag("--report"),
+        render: flag("--render"),
+        colour: flag("--colour") || flag("--color"),
+        save: flag("--save")
+    };
 
     let mut map = Grid {
-        width: input.len() as i32,
-        height: input[0].len() as i32,
-        antennas: Vec::new(),
-        antinodes: Vec::new()
+        width: input[0].len() as i32, // Number of columns
+        height: input.len() as i32, // Number of rows
+        antennas: Vec::new()
     };
 
     // Populate the map with anything not '.' as antennas
-    for i in 0..map.width {
-        for j in 0..map.height {
+    for i in 0..map.height {
+        for j in 0..map.width {
             let maybe_antenna: char = input[i as usize][j as usize];
             if maybe_antenna != '.' {
                 map.antennas.push((maybe_antenna, i, j));
@@ -158,6 +290,6 @@ fn main() {
         }
     }
 
-    part1(&mut map);
-    part2(&mut map);
-}
\ No newline at end of file
+    part1(&map, &options);
+    part2(&map, &options);
+}
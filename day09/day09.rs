@@ -1,208 +1,401 @@
 use std::fs;
+use std::fmt;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap};
 
-fn read_input(filename: &str) -> Vec<i32> {
+fn read_input(filename: &str) -> Vec<usize> {
     let contents: String = fs::read_to_string(filename).expect("Can't read file!");
-    let input: Vec<i32> = contents
-        .chars()
-        .filter(|&c| c != '\n') // There's a newline at the very end... 
-        .map(|c| c.to_digit(10).expect("Can't parse as digit...") as i32)
-        .collect();
-    return input
-}
-
-fn parse_to_disk(disk_map: &Vec<i32>) -> Vec<i32> {
-    // Naively the disk is Vec<i32> where each index is a block with the file id (-1 is empty)
-    // This might be WAY too big... We SHOULD deal with this in the condensed format, mathwise...
-    // It's only 70K blocks long? Is that what we expect?
-    // 20K * 0.5 (for half empty) * 5.5 (expected val. of 5.5 blocks for file) = 55K :)
-    let mut disk: Vec<i32> = Vec::new();
-    let mut file_flag: bool = true;
-    let mut file_id: i32 = 0;
-    for file_len in disk_map.into_iter() {
-        let new_files: Vec<i32>;
-        if file_flag {
-            // What happens in the edge case where file_len == 0 for an actual file?
-            assert!(*file_len != 0);
-            new_files = vec![file_id; (*file_len as usize).try_into().unwrap()];
-            file_id += 1;
-        } else {
-            new_files = vec![-1; (*file_len as usize).try_into().unwrap()];
-        }
-        file_flag = !file_flag; // Toggle between files and free space!
-        disk.extend(new_files);
-    }
-    return disk
-}
-
-fn parse_to_disk2(chunk_sizes: &Vec<i32>, chunk_ids: &Vec<i32>) -> Vec<i32> {
-    let mut disk: Vec<i32> = Vec::new();
-    for i in 0..chunk_sizes.len() {
-        let size = chunk_sizes[i];
-        let id = chunk_ids[i];
-        let new_files = vec![id; (size as usize).try_into().unwrap()];
-        disk.extend(new_files);
-    }
-    return disk
-}
-
-// Probably worse than just using .find and .reverse, but this is nicer somehow...
-fn move_head(disk: &Vec<i32>, head_start: usize, target: &str, direction: &str) -> Option<usize> {
-    let mut head = head_start;
-    let found_block = |block: i32| -> bool { // Oh it's like an anonymous function!
-        if target == "file" {
-            block >= 0
-        } else {
-            block < 0
-        }
-    };
-    while !found_block(disk[head]) {
-        if direction == "fwd" {
-            // Ensure we're not about to go out of bounds
-            if head == disk.len() - 1 {
-                return None
-            } else {
-                head += 1;
+    parse_disk_map(&contents, false).unwrap_or_else(|e| panic!("Bad disk map: {e}"))
+}
+
+/// Things that can be wrong with a disk map
+#[derive(Debug, PartialEq)]
+enum MapError {
+    Empty,
+    NotADigit { position: usize, found: char },
+    ZeroLengthFile { id: usize }
+}
+
+impl fmt::Display for MapError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MapError::Empty => write!(f, "there's nothing in it"),
+            MapError::NotADigit { position, found } => write!(f, "{found:?} at position {position} isn't a digit"),
+            MapError::ZeroLengthFile { id } => write!(f, "file {id} has length 0")
+        }
+    }
+}
+
+/// Odd lengths are fine (the puzzle's maps end on a file, so the last free space is implied).
+/// `allow_empty_files` is for maps from Disk::to_map, which uses 0-length files to split up long free runs.
+fn parse_disk_map(contents: &str, allow_empty_files: bool) -> Result<Vec<usize>, MapError> {
+    let mut disk_map: Vec<usize> = Vec::new();
+    for (position, c) in contents.trim_end().chars().enumerate() { // There's a newline at the very end...
+        let len = c.to_digit(10).ok_or(MapError::NotADigit { position, found: c })? as usize;
+        if position % 2 == 0 && len == 0 && !allow_empty_files {
+            return Err(MapError::ZeroLengthFile { id: position / 2 })
+        }
+        disk_map.push(len);
+    }
+    if disk_map.is_empty() {
+        return Err(MapError::Empty)
+    }
+    Ok(disk_map)
+}
+
+/// A run of blocks that all belong to the same file (or are all empty if id is None)
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Span {
+    id: Option<usize>,
+    offset: usize,
+    len: usize
+}
+
+/// The disk as spans instead of one entry per block. I used to expand it to every block, which was
+/// fine for part 1 but made part 2 a mess of Vec::insert :(
+/// Spans are sorted by offset, cover the whole disk, and there are never two empty spans in a row.
+#[derive(Clone, Debug)]
+struct Disk {
+    spans: Vec<Span>,
+    len: usize // Total number of blocks
+}
+
+impl Disk {
+    fn from_map(disk_map: &[usize]) -> Disk {
+        let mut files: Vec<Span> = Vec::new();
+        let mut offset: usize = 0;
+        for (i, &len) in disk_map.iter().enumerate() {
+            // Toggle between files and free space! (0-length files still use up an id, they just don't take any blocks)
+            if i % 2 == 0 && len > 0 {
+                files.push(Span { id: Some(i / 2), offset, len });
             }
-        } else {
-            // Ensure we're not about to overflow with usize
-            if head == 0 {
-                return None
-            } else {
-                head -= 1;
+            offset += len;
+        }
+        Disk::from_files(files, offset)
+    }
+
+    /// Builds the disk from just the file spans, filling in everything else as free space
+    fn from_files(mut files: Vec<Span>, len: usize) -> Disk {
+        files.sort_by_key(|span| span.offset);
+        let mut spans: Vec<Span> = Vec::new();
+        let mut offset: usize = 0;
+        for file in files.into_iter() {
+            assert!(file.offset >= offset, "Files overlap at block {}!", file.offset);
+            if file.offset > offset {
+                spans.push(Span { id: None, offset, len: file.offset - offset });
+            }
+            // Two pieces of the same file next to each other are really just one piece
+            match spans.last_mut() {
+                Some(last) if last.id == file.id && last.offset + last.len == file.offset => last.len += file.len,
+                _ => spans.push(file)
             }
+            offset = file.offset + file.len;
         }
+        if len > offset {
+            spans.push(Span { id: None, offset, len: len - offset });
+        }
+        Disk { spans, len }
     }
-    return Some(head);
+
+    fn files(&self) -> impl DoubleEndedIterator<Item = &Span> {
+        self.spans.iter().filter(|span| span.id.is_some())
+    }
+
+    fn free(&self) -> impl DoubleEndedIterator<Item = &Span> {
+        self.spans.iter().filter(|span| span.id.is_none())
+    }
+
+    /// Back to the dense disk map format. The format can't store ids (they just count up), so files get
+    /// renumbered in the order they appear. Runs longer than 9 get split up with empty files/free space in
+    /// between, so any disk works (but then you have to parse it with allow_empty_files).
+    fn to_map(&self) -> String {
+        let mut digits: Vec<usize> = Vec::new();
+        for span in self.spans.iter() {
+            let is_file = span.id.is_some();
+            let mut remaining = span.len;
+            while remaining > 0 {
+                // Even positions are files and odd ones are free space, so pad with a 0 if we're on the wrong one
+                if digits.len().is_multiple_of(2) != is_file {
+                    digits.push(0);
+                }
+                let chunk = std::cmp::min(remaining, 9);
+                digits.push(chunk);
+                remaining -= chunk;
+            }
+        }
+        digits.into_iter().map(|d| char::from_digit(d as u32, 10).unwrap()).collect()
+    }
+
+    /// Where the files and free space are, ignoring which file is which (since to_map forgets ids)
+    fn layout(&self) -> Vec<(bool, usize, usize)> {
+        self.spans.iter().map(|span| (span.id.is_some(), span.offset, span.len)).collect()
+    }
+
+    /// One character per block like the puzzle's examples: 00...111...2
+    /// Ids past 9 just show their last digit so everything still lines up.
+    fn dump(&self) -> String {
+        self.spans
+            .iter()
+            .map(|span| {
+                let c = match span.id {
+                    Some(id) => char::from_digit((id % 10) as u32, 10).unwrap(),
+                    None => '.'
+                };
+                c.to_string().repeat(span.len)
+            })
+            .collect()
+    }
+
+    fn checksum(&self) -> u64 {
+        // Sum of id * position over a span is id * (offset + ... + offset + len - 1), no need to go block by block
+        self.files()
+            .map(|span| {
+                let (offset, len) = (span.offset as u64, span.len as u64);
+                span.id.unwrap() as u64 * (len * offset + len * (len - 1) / 2)
+            })
+            .sum()
+    }
+}
+
+/// How fragmented the free space is. The free space at the very end doesn't count, since that's
+/// where compacting is supposed to put it.
+#[derive(Debug, PartialEq)]
+struct Fragmentation {
+    free_runs: usize,
+    largest_free_run: usize
+}
+
+impl Disk {
+    fn fragmentation(&self) -> Fragmentation {
+        let mut runs: Vec<&Span> = self.free().collect();
+        if runs.last().is_some_and(|span| span.offset + span.len == self.len) {
+            runs.pop();
+        }
+        Fragmentation {
+            free_runs: runs.len(),
+            largest_free_run: runs.iter().map(|span| span.len).max().unwrap_or(0)
+        }
+    }
+}
+
+/// Some way of squishing files to the left
+trait Strategy {
+    fn name(&self) -> String;
+    fn compact(&self, disk: &Disk) -> Disk;
 }
 
-fn checksum(disk: &Vec<i32>, enforce_empty_blocks: bool) -> i64 {
-    let mut sum: i64 = 0;
-    let mut seen_empty_blocks = false;
-    for (index, block) in disk.iter().enumerate() {
-        if block >= &0 { // How does it make sense that I have to borrow a zero literal?
-            if enforce_empty_blocks {
-                assert!(!seen_empty_blocks);
+/// Part 1: move blocks one at a time from the end of the disk into the leftmost free block.
+/// We never need to look at single blocks though, since a whole free span fills up at once.
+struct BlockByBlock;
+
+impl Strategy for BlockByBlock {
+    fn name(&self) -> String {
+        String::from("block by block")
+    }
+
+    fn compact(&self, disk: &Disk) -> Disk {
+        let mut free: Vec<Span> = disk.free().cloned().collect();
+        free.reverse(); // So the leftmost free span is at the end and we can pop it
+        let mut files: Vec<Span> = Vec::new();
+        for file in disk.files().rev() {
+            let mut remaining = file.len;
+            // Only free space to the left of the file is any use
+            while let Some(space) = free.last_mut().filter(|space| remaining > 0 && space.offset < file.offset) {
+                let moved = std::cmp::min(space.len, remaining);
+                files.push(Span { id: file.id, offset: space.offset, len: moved });
+                space.offset += moved;
+                space.len -= moved;
+                remaining -= moved;
+                if space.len == 0 {
+                    free.pop();
+                }
+            }
+            // We take blocks off the END of the file, so whatever's left is still at the start
+            if remaining > 0 {
+                files.push(Span { id: file.id, offset: file.offset, len: remaining });
             }
-            sum += &(index as i64) * &(*block as i64);
-        } else {
-            seen_empty_blocks = true;
         }
+        Disk::from_files(files, disk.len)
     }
-    return sum
 }
 
-fn find_empty_chunk(chunk_sizes: &Vec<i32>, chunk_ids: &Vec<i32>, this_size: i32, this_index: usize) -> Option<usize> {
-    for index in 0..this_index { // Loop starting at the left until this_index (exclusive)
-        if chunk_ids[index] == -1 { // Only consider open spots
-            if chunk_sizes[index] >= this_size { // With enough size
-                return Some(index)
+/// Which free span a whole file goes into (it's always one to the left of the file, or the file stays put)
+#[derive(Clone, Copy, Debug)]
+enum Fit {
+    Leftmost, // Part 2
+    Best,     // Smallest span that fits (leftmost if there's a tie)
+    Worst     // Biggest span (leftmost if there's a tie)
+}
+
+/// Moves whole files, either highest id first (part 2) or lowest id first
+struct WholeFiles {
+    fit: Fit,
+    ascending_ids: bool
+}
+
+/// Free spans indexed by offset (so freed up space can merge with its neighbours) and by size
+/// (a min-heap of offsets for each size, so finding the leftmost span of a given size is quick).
+/// The heaps can have stale entries for spans that have since been used or merged, which we skip.
+struct FreeIndex {
+    by_offset: BTreeMap<usize, usize>, // offset -> len
+    by_size: BTreeMap<usize, BinaryHeap<Reverse<usize>>>
+}
+
+impl FreeIndex {
+    fn new(disk: &Disk) -> FreeIndex {
+        let mut index = FreeIndex { by_offset: BTreeMap::new(), by_size: BTreeMap::new() };
+        for space in disk.free() {
+            index.insert(space.offset, space.len);
+        }
+        index
+    }
+
+    fn insert(&mut self, mut offset: usize, mut len: usize) {
+        // Merge with the free span right before and right after this one
+        if let Some((&before_offset, &before_len)) = self.by_offset.range(..offset).next_back() {
+            if before_offset + before_len == offset {
+                self.by_offset.remove(&before_offset);
+                offset = before_offset;
+                len += before_len;
             }
         }
+        if let Some(after_len) = self.by_offset.remove(&(offset + len)) {
+            len += after_len;
+        }
+        self.by_offset.insert(offset, len);
+        self.by_size.entry(len).or_default().push(Reverse(offset));
     }
-    return None
-}
-
-fn part1(input: &Vec<i32>) {
-    let mut disk = parse_to_disk(input); 
-    let mut fwd_head: Option<usize> = move_head(&disk, 0, "empty", "fwd"); // Start at first empty block
-    let mut rev_head: Option<usize> = move_head(&disk, disk.len() - 1, "file", "rev"); // Start at last file block
-    let mut done: bool = false;
-
-    while !done {
-        match (fwd_head, rev_head) {
-            (Some(fwd), Some(rev)) => {
-                // Make sure the heads haven't passed each other!
-                if fwd > rev { break }
-                // println!("{}", disk.iter().map(|x| if *x == -1 { ".".to_string() } else { x.to_string() }).collect::<String>());
-                // println!("{}", disk.iter().enumerate().map(|(i,_)| if i == Some(fwd_head) || i == Some(rev_head) { "^" } else { "-" }).collect::<String>());
-                // Swap the two blocks
-                let temp_left = disk[fwd as usize];
-                let temp_right = disk[rev as usize];
-                assert!((temp_left == -1) && temp_right >= 0);
-                disk[fwd as usize] = temp_right;
-                disk[rev as usize] = temp_left;
-                // Move the heads
-                fwd_head = move_head(&disk, fwd, "empty", "fwd");
-                rev_head = move_head(&disk, rev, "file", "rev");
+
+    /// Leftmost free span of exactly this size
+    fn leftmost(&mut self, size: usize) -> Option<usize> {
+        let heap = self.by_size.get_mut(&size)?;
+        while let Some(&Reverse(offset)) = heap.peek() {
+            if self.by_offset.get(&offset) == Some(&size) {
+                return Some(offset)
             }
-            _ => done = true,
-        }
-    }
-    println!("{}", checksum(&disk, true));
-}
-
-fn part2(input: &Vec<i32>) {
-    // Harder to work with the actual disk, so let's work with the input chunks
-    let mut chunk_sizes: Vec<i32> = input.clone();
-    let mut chunk_ids: Vec<i32> = Vec::new();
-    let mut file_flag: bool = true;
-    let mut file_id: i32 = 0;
-    for _ in chunk_sizes.iter() {
-        if file_flag {
-            chunk_ids.push(file_id);
-            file_id += 1;
-        } else {
-            chunk_ids.push(-1);
-        }
-        file_flag = !file_flag;
-    }
-    assert!(chunk_sizes.len() == chunk_ids.len());
-
-    // Let's sort chunks in this format (we can't do a for loop because we're modifying so many things)
-    let mut head: usize = chunk_sizes.len() - 1;
-    while head >= 0 {
-        // let disk = parse_to_disk2(&chunk_sizes, &chunk_ids);
-        // println!("{}", disk.iter().map(|x| if *x == -1 { ".".to_string() } else { x.to_string() }).collect::<String>());
-        // Look for empty block given current chunks
-        let size = chunk_sizes[head];
-        let id = chunk_ids[head];
-
-        if id != -1 { // Skip swapping empty chunks (without this I failed but not sure why since swapping empty chunks should be equivalent)
-            let chunk_result: Option<usize> = find_empty_chunk(&chunk_sizes, &chunk_ids, size, head);
-            // Check and move chunks
-            match chunk_result {
-                Some(left_i) => {
-
-                    // Make sure it's left of current index! Function doesn't check :)
-                    let right_i = head.clone();
-                    assert!(left_i < right_i);
-                    assert!(chunk_ids[left_i] == -1);
-
-                    // Do a direct swap
-                    chunk_sizes[right_i] = chunk_sizes[left_i];
-                    chunk_ids[right_i] = chunk_ids[left_i];
-                    chunk_sizes[left_i] = size;
-                    chunk_ids[left_i] = id;
-
-                    // But we may need to add chunks and adjust sizes!
-                    if chunk_sizes[left_i] < chunk_sizes[right_i] {
-                        let size_diff: i32 = chunk_sizes[right_i] - chunk_sizes[left_i];
-                        chunk_sizes[right_i] -= size_diff; // Take away free empty space that got moved right
-                        // Add an empty space chunk after the file chunk that got moved left
-                        chunk_sizes.insert(left_i + 1, size_diff);
-                        chunk_ids.insert(left_i + 1, -1);
-                    } 
-                }
-                _ => {}
+            heap.pop(); // Stale!
         }
-        
+        self.by_size.remove(&size); // Nothing left this size, so stop looking here
+        None
+    }
+
+    fn take(&mut self, offset: usize, used: usize) {
+        let len = self.by_offset.remove(&offset).expect("That span isn't free!");
+        if len > used {
+            self.by_offset.insert(offset + used, len - used);
+            self.by_size.entry(len - used).or_default().push(Reverse(offset + used));
         }
-        // Decrement counter
-        // If I moved things around, I won't have to worry about underflow, but this is cleaner.
-        if head == 0 {
-            break
-        } else {
-            head -= 1;
+    }
+}
+
+impl Strategy for WholeFiles {
+    fn name(&self) -> String {
+        let order = if self.ascending_ids { "lowest id first" } else { "highest id first" };
+        format!("whole files, {:?} fit, {order}", self.fit).to_lowercase()
+    }
+
+    fn compact(&self, disk: &Disk) -> Disk {
+        let mut free = FreeIndex::new(disk);
+        let mut files: Vec<Span> = disk.files().cloned().collect();
+        files.sort_by_key(|file| file.id);
+        if !self.ascending_ids {
+            files.reverse();
+        }
+        for file in files.iter_mut() {
+            // The leftmost span of each size that fits, as long as it's to the left of the file
+            let sizes: Vec<usize> = free.by_size.range(file.len..).map(|(&size, _)| size).collect();
+            let candidates: Vec<(usize, usize)> = sizes
+                .into_iter()
+                .filter_map(|size| free.leftmost(size).map(|offset| (size, offset)))
+                .filter(|&(_, offset)| offset < file.offset)
+                .collect();
+            let choice = match self.fit {
+                Fit::Leftmost => candidates.into_iter().min_by_key(|&(_, offset)| offset),
+                Fit::Best => candidates.into_iter().min_by_key(|&(size, offset)| (size, offset)),
+                Fit::Worst => candidates.into_iter().min_by_key(|&(size, offset)| (Reverse(size), offset))
+            };
+            if let Some((_, offset)) = choice {
+                free.take(offset, file.len);
+                // Going lowest id first, the space we leave behind can be useful for files further right.
+                // Highest id first, everything we haven't moved yet is to the left of it, so don't bother.
+                if self.ascending_ids {
+                    free.insert(file.offset, file.len);
+                }
+                file.offset = offset;
+            }
         }
+        Disk::from_files(files, disk.len)
+    }
+}
+
+fn part1(input: &[usize], dump: bool) {
+    let disk = Disk::from_map(input);
+    let compacted = BlockByBlock.compact(&disk);
+    // Everything should be squished to the left now
+    assert!(compacted.fragmentation().free_runs == 0);
+    if dump {
+        println!("{}\n{}", disk.dump(), compacted.dump());
+    }
+    println!("{}", compacted.checksum());
+}
+
+fn part2(input: &[usize], dump: bool) {
+    let disk = Disk::from_map(input);
+    let compacted = WholeFiles { fit: Fit::Leftmost, ascending_ids: false }.compact(&disk);
+    if dump {
+        println!("{}\n{}", disk.dump(), compacted.dump());
     }
+    println!("{}", compacted.checksum());
+}
 
-    // Parse to disk then calculate checksum
-    let disk = parse_to_disk2(&chunk_sizes, &chunk_ids);
-    println!("{}", checksum(&disk, false));
+/// `cargo run -- --round-trip`: every disk should come back the same from its disk map
+fn check_round_trips(input: &[usize]) {
+    let disk = Disk::from_map(input);
+    let mut disks: Vec<(String, Disk)> = vec![(String::from("original"), disk.clone())];
+    disks.push((BlockByBlock.name(), BlockByBlock.compact(&disk)));
+    for fit in [Fit::Leftmost, Fit::Best, Fit::Worst] {
+        let strategy = WholeFiles { fit, ascending_ids: false };
+        disks.push((strategy.name(), strategy.compact(&disk)));
+    }
+    for (name, disk) in disks.iter() {
+        let disk_map = disk.to_map();
+        let parsed = parse_disk_map(&disk_map, true).unwrap_or_else(|e| panic!("{name} made a bad disk map: {e}"));
+        let round_trip = Disk::from_map(&parsed);
+        assert!(round_trip.layout() == disk.layout() && round_trip.len == disk.len, "{name} didn't survive the round trip!");
+        println!("{name}: ok ({} digits)", disk_map.len());
+    }
+}
+
+/// `cargo run -- --compare` to see how each strategy does
+fn compare_strategies(input: &[usize]) {
+    let disk = Disk::from_map(input);
+    let mut strategies: Vec<Box<dyn Strategy>> = vec![Box::new(BlockByBlock)];
+    for ascending_ids in [false, true] {
+        for fit in [Fit::Leftmost, Fit::Best, Fit::Worst] {
+            strategies.push(Box::new(WholeFiles { fit, ascending_ids }));
+        }
+    }
+    let before = disk.fragmentation();
+    println!("Before: {} free runs, largest is {}", before.free_runs, before.largest_free_run);
+    for strategy in strategies.iter() {
+        let compacted = strategy.compact(&disk);
+        let after = compacted.fragmentation();
+        println!("{:>45}: checksum {:>16}, {:>5} free runs, largest is {}",
+            strategy.name(), compacted.checksum(), after.free_runs, after.largest_free_run);
+    }
 }
 
 fn main() {
     let input = read_input("input.txt");
-    part1(&input);
-    part2(&input);
-}
\ No newline at end of file
+    if std::env::args().any(|arg| arg == "--compare") {
+        compare_strategies(&input);
+        return
+    }
+    if std::env::args().any(|arg| arg == "--round-trip") {
+        check_round_trips(&input);
+        return
+    }
+    let dump = std::env::args().any(|arg| arg == "--dump"); // Only a good idea for the examples!
+    part1(&input, dump);
+    part2(&input, dump);
+}
@@ -1,80 +1,304 @@
-use std::fs;
-use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
 
-fn read_input(filename: &str) -> Vec<Vec<u32>> {
-    let mut output: Vec<Vec<u32>> = Vec::new();
+/// Heights, with None for '.' (impassable, like in some of the puzzle examples)
+type HeightMap = Vec<Vec<Option<u32>>>;
+
+fn read_input(filename: &str) -> HeightMap {
+    let mut output: HeightMap = Vec::new();
     let contents = fs::read_to_string(filename).expect("Can't read file!");
     for line in contents.lines() {
-        output.push(line.chars().map(|x| x.to_digit(10).unwrap() as u32).collect());
-    }
-    return output
-}
-
-fn find_trailends(trailhead: (usize, usize), map: &Vec<Vec<u32>>) -> HashSet<(usize, usize)> {
-    let mut nines: HashSet<(usize, usize)> = HashSet::new();
-    let this_height: u32 = map[trailhead.0][trailhead.1];
-    // println!("New branch at ({}, {}) at heigh   t {}", trailhead.0, trailhead.1, this_height);
-    for (x, y) in vec![(-1, 0), (1, 0), (0, -1), (0, 1)] {
-        // Adding i32 to usize and clamping is annoying...
-        let next_x: usize = (((trailhead.0 as i32) + x).max(0) as usize).min(map.len() - 1);
-        let next_y: usize = (((trailhead.1 as i32) + y).max(0) as usize).min(map[0].len() - 1);
-        let next_height: u32 = map[next_x][next_y];
-        if this_height + 1 == next_height {
-            if next_height == 9 {
-                // println!("Completed trail at ({}, {})", next_x, next_y);
-                nines.insert((next_x, next_y)); // Don't return here! We have to complete the loop!
+        output.push(line.chars().map(|c| match c {
+            '.' => None,
+            _ => Some(c.to_digit(10).unwrap_or_else(|| panic!("Can't parse {c:?} as a height!")))
+        }).collect());
+    }
+    output
+}
+
+/// What counts as a hiking trail. The puzzle's rules are PUZZLE_RULES.
+#[derive(Clone, Copy, Debug)]
+struct TrailRules {
+    min_step: i32, // Allowed change in height for each step (inclusive)...
+    max_step: i32, // ...which has to be all uphill or all downhill, so trails can't go in circles
+    diagonal: bool, // 8 neighbours instead of 4
+    start: u32, // Height of trailheads
+    end: u32 // Height where trails end
+}
+
+const PUZZLE_RULES: TrailRules = TrailRules { min_step: 1, max_step: 1, diagonal: false, start: 0, end: 9 };
+
+impl TrailRules {
+    /// e.g. `cargo run -- --step=1..2 --diagonal --start=0 --end=8` (--step=-1 for downhill trails)
+    fn from_args() -> TrailRules {
+        let mut rules = PUZZLE_RULES;
+        for arg in std::env::args().skip(1) {
+            let parse = |value: &str| -> i32 { value.parse().unwrap_or_else(|_| panic!("Can't parse {value:?} in {arg}")) };
+            if let Some(steps) = arg.strip_prefix("--step=") {
+                match steps.split_once("..") {
+                    Some((min, max)) => (rules.min_step, rules.max_step) = (parse(min), parse(max)),
+                    None => (rules.min_step, rules.max_step) = (parse(steps), parse(steps))
+                }
+            } else if let Some(start) = arg.strip_prefix("--start=") {
+                rules.start = parse(start) as u32;
+            } else if let Some(end) = arg.strip_prefix("--end=") {
+                rules.end = parse(end) as u32;
+            } else if arg == "--diagonal" {
+                rules.diagonal = true;
             }
-            nines.extend(find_trailends((next_x, next_y), map));
         }
+        if let Err(e) = rules.check() {
+            panic!("Bad trail rules: {e}");
+        }
+        rules
+    }
+
+    fn check(&self) -> Result<(), String> {
+        if self.min_step > self.max_step {
+            return Err(format!("step range {}..{} is empty", self.min_step, self.max_step))
+        }
+        if self.min_step <= 0 && self.max_step >= 0 {
+            return Err(String::from("steps have to be all uphill or all downhill (or you can walk in circles)"))
+        }
+        if self.uphill() != (self.end > self.start) {
+            return Err(format!("can't get from {} to {} going {}", self.start, self.end, if self.uphill() { "uphill" } else { "downhill" }))
+        }
+        Ok(())
+    }
+
+    fn uphill(&self) -> bool {
+        self.min_step > 0
+    }
+
+    fn can_step(&self, from: u32, to: u32) -> bool {
+        let delta = to as i32 - from as i32;
+        (self.min_step..=self.max_step).contains(&delta)
+    }
+
+    /// The cells around this one that are actually on the map (no more clamping cells onto themselves!)
+    fn neighbours<'a>(&self, map: &'a HeightMap, cell: (usize, usize)) -> impl Iterator<Item = (usize, usize)> + 'a {
+        // Straight ones first, so we can just stop after 4 if diagonals aren't allowed
+        const DIRECTIONS: [(i32, i32); 8] = [(-1, 0), (1, 0), (0, -1), (0, 1), (-1, -1), (-1, 1), (1, -1), (1, 1)];
+        let count = if self.diagonal { 8 } else { 4 };
+        DIRECTIONS[..count].iter().filter_map(move |&(dx, dy)| {
+            let next_x = cell.0.checked_add_signed(dx as isize)?;
+            let next_y = cell.1.checked_add_signed(dy as isize)?;
+            if next_x < map.len() && next_y < map[next_x].len() { Some((next_x, next_y)) } else { None }
+        })
     }
-    return nines
 }
 
-// Lol! I accidentally counted possible trails instead of trail endings for part 1, so this will be easy!
-fn rate_trailhead(trailhead: (usize, usize), map: &Vec<Vec<u32>>) -> u32 {
-    let mut rating: u32 = 0;
-    let this_height: u32 = map[trailhead.0][trailhead.1];
-    for (x, y) in vec![(-1, 0), (1, 0), (0, -1), (0, 1)] {
-        // Adding i32 to usize and clamping is annoying...
-        let next_x: usize = (((trailhead.0 as i32) + x).max(0) as usize).min(map.len() - 1);
-        let next_y: usize = (((trailhead.1 as i32) + y).max(0) as usize).min(map[0].len() - 1);
-        let next_height: u32 = map[next_x][next_y];
-        if this_height + 1 == next_height {
-            if next_height == 9 {
-                rating += 1;
-            }
-            rating += rate_trailhead((next_x, next_y), map)
+/// A set of trail ends, one bit each
+#[derive(Clone)]
+struct BitSet {
+    words: Vec<u64>
+}
+
+impl BitSet {
+    fn new(size: usize) -> BitSet {
+        BitSet { words: vec![0; size.div_ceil(64)] }
+    }
+
+    fn insert(&mut self, i: usize) {
+        self.words[i / 64] |= 1 << (i % 64);
+    }
+
+    fn union_with(&mut self, other: &BitSet) {
+        for (word, other_word) in self.words.iter_mut().zip(other.words.iter()) {
+            *word |= other_word;
         }
     }
-    return rating
+
+    fn count(&self) -> usize {
+        self.words.iter().map(|word| word.count_ones() as usize).sum()
+    }
 }
 
-fn part1(map: &Vec<Vec<u32>>) {
-    let mut score: u32 = 0;
-    for x in 0..map.len() {
-        for y in 0..map[0].len() {
-            if map[x][y] == 0 {
-                score += find_trailends((x, y), map).len() as u32;
+/// For every cell: which trail ends you can reach from it and how many different ways there are to get to one.
+/// Recursing from every trailhead re-walks the same bits of trail over and over, so instead we start at the
+/// trail ends and work back. Trails only ever go one way in height, so if we do the cells closest to the end
+/// height first, everything a cell can step to is already done by the time we get to it.
+struct Trails {
+    reachable_ends: Vec<Vec<BitSet>>,
+    paths: Vec<Vec<u64>>
+}
+
+fn analyse_trails(map: &HeightMap, rules: &TrailRules) -> Trails {
+    let mut cells: Vec<((usize, usize), u32)> = Vec::new();
+    for (x, row) in map.iter().enumerate() {
+        for (y, height) in row.iter().enumerate() {
+            if let Some(height) = height {
+                cells.push(((x, y), *height));
             }
         }
     }
-    println!("{}", score);
+    cells.sort_by_key(|&(_, height)| height);
+    if rules.uphill() {
+        cells.reverse();
+    }
+
+    let end_count = cells.iter().filter(|&&(_, height)| height == rules.end).count();
+    let mut trails = Trails {
+        reachable_ends: map.iter().map(|row| vec![BitSet::new(end_count); row.len()]).collect(),
+        paths: map.iter().map(|row| vec![0; row.len()]).collect()
+    };
+    let mut end_index = 0;
+    for &((x, y), height) in cells.iter() {
+        if height == rules.end {
+            // Trails stop here, even if you could keep going
+            trails.reachable_ends[x][y].insert(end_index);
+            trails.paths[x][y] = 1;
+            end_index += 1;
+            continue
+        }
+        for (next_x, next_y) in rules.neighbours(map, (x, y)) {
+            if map[next_x][next_y].is_some_and(|next_height| rules.can_step(height, next_height)) {
+                let next_ends = trails.reachable_ends[next_x][next_y].clone();
+                trails.reachable_ends[x][y].union_with(&next_ends);
+                trails.paths[x][y] += trails.paths[next_x][next_y];
+            }
+        }
+    }
+    trails
+}
+
+fn trailheads<'a>(map: &'a HeightMap, rules: &TrailRules) -> impl Iterator<Item = (usize, usize)> + 'a {
+    let start = rules.start;
+    map.iter().enumerate().flat_map(move |(x, row)| {
+        row.iter().enumerate().filter(move |(_, &height)| height == Some(start)).map(move |(y, _)| (x, y))
+    })
+}
+
+/// Every trail from one trailhead, one at a time as the cells along it. There can be a LOT of them, so
+/// this walks depth-first and only keeps the current trail and the steps left to try from each cell on it.
+struct TrailIter<'a> {
+    map: &'a HeightMap,
+    rules: &'a TrailRules,
+    trails: &'a Trails,
+    path: Vec<(usize, usize)>,
+    untried: Vec<Vec<(usize, usize)>> // Steps we haven't taken yet from each cell in path
+}
+
+impl<'a> TrailIter<'a> {
+    fn new(map: &'a HeightMap, rules: &'a TrailRules, trails: &'a Trails, trailhead: (usize, usize)) -> TrailIter<'a> {
+        let mut iter = TrailIter { map, rules, trails, path: Vec::new(), untried: Vec::new() };
+        if map[trailhead.0][trailhead.1] == Some(rules.start) {
+            iter.path.push(trailhead);
+            iter.untried.push(iter.next_steps(trailhead));
+        }
+        iter
+    }
+
+    /// Only steps that still lead to a trail end (analyse_trails already knows which ones do)
+    fn next_steps(&self, (x, y): (usize, usize)) -> Vec<(usize, usize)> {
+        let height = self.map[x][y].unwrap();
+        let mut steps: Vec<(usize, usize)> = self.rules.neighbours(self.map, (x, y)).filter(|&(next_x, next_y)| {
+            self.map[next_x][next_y].is_some_and(|next_height| self.rules.can_step(height, next_height))
+                && self.trails.paths[next_x][next_y] > 0
+        }).collect();
+        steps.reverse(); // We pop from the back, so this tries them in neighbours() order
+        steps
+    }
 }
 
-fn part2(map: &Vec<Vec<u32>>) {
-    let mut score: u32 = 0;
-    for x in 0..map.len() {
-        for y in 0..map[0].len() {
-            if map[x][y] == 0 {
-                score += rate_trailhead((x, y), map);
+impl Iterator for TrailIter<'_> {
+    type Item = Vec<(usize, usize)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let Some(cell) = self.untried.last_mut()?.pop() else {
+                // Nothing left to try from here, so back up a step
+                self.untried.pop();
+                self.path.pop();
+                continue
+            };
+            self.path.push(cell);
+            if self.map[cell.0][cell.1] == Some(self.rules.end) {
+                let trail = self.path.clone();
+                self.path.pop();
+                return Some(trail)
             }
+            let steps = self.next_steps(cell);
+            self.untried.push(steps);
         }
     }
+}
+
+/// The map with only the cells on some trail from the trailhead left in (like the drawings in the puzzle)
+fn draw_trails(map: &HeightMap, on_trail: &[Vec<bool>]) -> String {
+    let mut output = String::new();
+    for (row, on_trail_row) in map.iter().zip(on_trail.iter()) {
+        for (height, &on_trail) in row.iter().zip(on_trail_row.iter()) {
+            output.push(match height {
+                Some(height) if on_trail => char::from_digit(*height, 10).unwrap(),
+                _ => '.'
+            });
+        }
+        output.push('\n');
+    }
+    output
+}
+
+fn write_to_txt(drawing: &str, fname: String) -> io::Result<()> {
+    let file = File::create(fname)?;
+    let mut writer = BufWriter::new(file);
+    write!(writer, "{}", drawing)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// e.g. `cargo run -- --trails-from=0,2 --list --save` to see what's going on with one trailhead's rating
+fn show_trails(map: &HeightMap, rules: &TrailRules, trails: &Trails, trailhead: (usize, usize)) {
+    let (x, y) = trailhead;
+    if map.get(x).and_then(|row| row.get(y)).copied().flatten() != Some(rules.start) {
+        eprintln!("({x}, {y}) isn't a trailhead!");
+        return
+    }
+    let list = std::env::args().any(|arg| arg == "--list");
+    let mut on_trail: Vec<Vec<bool>> = map.iter().map(|row| vec![false; row.len()]).collect();
+    let mut count: u64 = 0;
+    for trail in TrailIter::new(map, rules, trails, trailhead) {
+        if list {
+            println!("{}", trail.iter().map(|(x, y)| format!("({x}, {y})")).collect::<Vec<String>>().join(" -> "));
+        }
+        for &(x, y) in trail.iter() {
+            on_trail[x][y] = true;
+        }
+        count += 1;
+    }
+    // These should always match, otherwise one of them is wrong!
+    println!("Trails from ({x}, {y}): {count} walked, {} counted", trails.paths[x][y]);
+    let drawing = draw_trails(map, &on_trail);
+    print!("{}", drawing);
+    if std::env::args().any(|arg| arg == "--save") {
+        if let Err(e) = write_to_txt(&drawing, format!("trails_{x}_{y}.txt")) {
+            eprintln!("Failed to write to file: {}", e);
+        }
+    }
+}
+
+fn part1(map: &HeightMap, rules: &TrailRules, trails: &Trails) {
+    let score: usize = trailheads(map, rules).map(|(x, y)| trails.reachable_ends[x][y].count()).sum();
     println!("{}", score);
 }
 
+// Lol! I accidentally counted possible trails instead of trail endings for part 1, so this will be easy!
+fn part2(map: &HeightMap, rules: &TrailRules, trails: &Trails) {
+    let rating: u64 = trailheads(map, rules).map(|(x, y)| trails.paths[x][y]).sum();
+    println!("{}", rating);
+}
+
 fn main() {
     let input = read_input("input.txt");
-    part1(&input);
-    part2(&input);
-}
\ No newline at end of file
+    let rules = TrailRules::from_args();
+    let trails = analyse_trails(&input, &rules);
+    part1(&input, &rules, &trails);
+    part2(&input, &rules, &trails);
+    for arg in std::env::args() {
+        if let Some(cell) = arg.strip_prefix("--trails-from=") {
+            let parse = |value: &str| -> usize { value.trim().parse().unwrap_or_else(|_| panic!("Can't parse {value:?} in {arg}")) };
+            let (x, y) = cell.split_once(',').unwrap_or_else(|| panic!("Expected --trails-from=row,column, not {arg}"));
+            show_trails(&input, &rules, &trails, (parse(x), parse(y)));
+        }
+    }
+}
@@ -1,67 +1,520 @@
-use std::fs;
-use memoize::memoize;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use num::BigUint;
 
-fn read_input(filename: &str) -> Vec<i64> {
+type Stone = u64;
+
+/// How many stones have each number on them. The order of the stones never actually matters, and there are
+/// only a few thousand different numbers that ever show up, so this stays small even when the count is huge.
+type StoneCounts = HashMap<Stone, BigUint>;
+
+fn read_input(filename: &str) -> Vec<Stone> {
     let contents: String = fs::read_to_string(filename).expect("Can't read file!");
-    let input: Vec<i64> = contents
+    contents
         .split_whitespace()
         .filter_map(|x| x.parse().ok())
-        .collect();
-    return input
-}
-
-fn blink(stones: Vec<i64>) -> Vec<i64> { // Consume the original stone list since we're replacing it
-    // It's actually easier to work with these as strings funnily enough...
-    let mut new_stones: Vec<i64> = Vec::new();
-    for s in stones.into_iter() {
-        let stone_string: String = s.to_string();
-        if stone_string == "0" { 
-            new_stones.push(1);
-        } else if stone_string.len() % 2 == 0 {
-            let left_stone_string: &str = &stone_string[..stone_string.len()/2];
-            let right_stone_string:&str = &stone_string[stone_string.len()/2..];
-            new_stones.push(left_stone_string.parse::<i64>().unwrap());
-            new_stones.push(right_stone_string.parse::<i64>().unwrap());
-        } else {
-            new_stones.push(s * 2024);
-        }
-    }
-    return new_stones
-}
-
-#[memoize]
-fn count_stones(stone_number: i64, blinks: u32) -> u64 {
-    let new_stones = blink(vec![stone_number]);
-    if blinks == 1 { // This is the end of the recursion 
-        return new_stones.len() as u64
+        .collect()
+}
+
+fn digit_count(stone: Stone) -> u32 {
+    stone.checked_ilog10().unwrap_or(0) + 1
+}
+
+/// Which stones a rule applies to
+#[derive(Clone, Copy, Debug)]
+enum Predicate {
+    Any,
+    Equals(Stone),
+    DigitsMultipleOf(u32), // "even_digits" is DigitsMultipleOf(2)
+    DivisibleBy(Stone)
+}
+
+impl Predicate {
+    fn matches(&self, stone: Stone) -> bool {
+        match *self {
+            Predicate::Any => true,
+            Predicate::Equals(n) => stone == n,
+            Predicate::DigitsMultipleOf(k) => digit_count(stone).is_multiple_of(k),
+            Predicate::DivisibleBy(n) => stone.is_multiple_of(n)
+        }
+    }
+}
+
+/// What a stone turns into (checked, since a stone that overflows would just give nonsense counts)
+#[derive(Clone, Copy, Debug)]
+enum Transform {
+    Set(Stone),
+    Multiply(Stone),
+    Add(Stone),
+    Split(u32) // Into this many pieces of digits (the first piece gets any leftover digits)
+}
+
+impl Transform {
+    fn apply(&self, stone: Stone) -> Vec<Stone> {
+        let too_big = || panic!("Stone {stone} got too big for {self:?}!");
+        match *self {
+            Transform::Set(n) => vec![n],
+            Transform::Multiply(n) => vec![stone.checked_mul(n).unwrap_or_else(too_big)],
+            Transform::Add(n) => vec![stone.checked_add(n).unwrap_or_else(too_big)],
+            Transform::Split(1) => vec![stone], // The whole stone (10^digits might not even fit in a u64!)
+            Transform::Split(pieces) => {
+                let digits = digit_count(stone);
+                let piece_digits = digits / pieces;
+                let piece_size = 10_u64.pow(piece_digits);
+                let mut rest = stone;
+                let mut output: Vec<Stone> = vec![0; pieces as usize];
+                for piece in output.iter_mut().skip(1).rev() {
+                    *piece = rest % piece_size;
+                    rest /= piece_size;
+                }
+                output[0] = rest;
+                output
+            }
+        }
+    }
+}
+
+struct Rule {
+    predicate: Predicate,
+    transform: Transform
+}
+
+/// The first rule that matches a stone is the one that happens to it. Stones that don't match anything stay the same.
+struct RuleSet {
+    rules: Vec<Rule>
+}
+
+#[derive(Debug)]
+enum RuleError {
+    Empty,
+    BadLine { line: usize, text: String },
+    BadPredicate { line: usize, text: String },
+    BadTransform { line: usize, text: String }
+}
+
+impl fmt::Display for RuleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RuleError::Empty => write!(f, "there aren't any rules"),
+            RuleError::BadLine { line, text } => write!(f, "line {line} ({text:?}) should look like `predicate => transform`"),
+            RuleError::BadPredicate { line, text } => write!(f, "line {line}: don't know the predicate {text:?}"),
+            RuleError::BadTransform { line, text } => write!(f, "line {line}: don't know the transform {text:?}")
+        }
+    }
+}
+
+impl RuleSet {
+    fn puzzle() -> RuleSet {
+        RuleSet { rules: vec![
+            Rule { predicate: Predicate::Equals(0), transform: Transform::Set(1) },
+            Rule { predicate: Predicate::DigitsMultipleOf(2), transform: Transform::Split(2) },
+            Rule { predicate: Predicate::Any, transform: Transform::Multiply(2024) }
+        ] }
+    }
+
+    /// One rule per line, in order, like this (the puzzle's rules):
+    /// ```text
+    /// # Comments and blank lines are ignored
+    /// equals 0 => set 1
+    /// even_digits => split 2
+    /// any => multiply 2024
+    /// ```
+    /// Predicates: `any`, `equals N`, `even_digits`, `digits_multiple_of K`, `divisible_by N`.
+    /// Transforms: `set N`, `multiply N`, `add N`, `split K`.
+    fn parse(contents: &str) -> Result<RuleSet, RuleError> {
+        let mut rules: Vec<Rule> = Vec::new();
+        for (i, text) in contents.lines().enumerate() {
+            let line = i + 1;
+            let text = text.trim();
+            if text.is_empty() || text.starts_with('#') {
+                continue
+            }
+            let Some((predicate, transform)) = text.split_once("=>") else {
+                return Err(RuleError::BadLine { line, text: text.to_string() })
+            };
+            let predicate = parse_predicate(predicate.trim()).ok_or_else(|| RuleError::BadPredicate { line, text: predicate.trim().to_string() })?;
+            let transform = parse_transform(transform.trim()).ok_or_else(|| RuleError::BadTransform { line, text: transform.trim().to_string() })?;
+            rules.push(Rule { predicate, transform });
+        }
+        if rules.is_empty() {
+            return Err(RuleError::Empty)
+        }
+        Ok(RuleSet { rules })
+    }
+
+    fn apply(&self, stone: Stone) -> Vec<Stone> {
+        match self.rules.iter().find(|rule| rule.predicate.matches(stone)) {
+            Some(rule) => rule.transform.apply(stone),
+            None => vec![stone]
+        }
+    }
+}
+
+/// Splits "name 123" into ("name", Some(123)), or ("name", None) if there's no number
+fn name_and_number(text: &str) -> Option<(&str, Option<u64>)> {
+    let mut words = text.split_whitespace();
+    let name = words.next()?;
+    let number = match words.next() {
+        Some(word) => Some(word.parse().ok()?),
+        None => None
+    };
+    if words.next().is_some() { None } else { Some((name, number)) }
+}
+
+fn parse_predicate(text: &str) -> Option<Predicate> {
+    match name_and_number(text)? {
+        ("any", None) => Some(Predicate::Any),
+        ("even_digits", None) => Some(Predicate::DigitsMultipleOf(2)),
+        ("equals", Some(n)) => Some(Predicate::Equals(n)),
+        ("digits_multiple_of", Some(k)) if k > 0 => Some(Predicate::DigitsMultipleOf(k.try_into().ok()?)),
+        ("divisible_by", Some(n)) if n > 0 => Some(Predicate::DivisibleBy(n)),
+        _ => None
+    }
+}
+
+fn parse_transform(text: &str) -> Option<Transform> {
+    match name_and_number(text)? {
+        ("set", Some(n)) => Some(Transform::Set(n)),
+        ("multiply", Some(n)) => Some(Transform::Multiply(n)),
+        ("add", Some(n)) => Some(Transform::Add(n)),
+        ("split", Some(k)) if (1..=20).contains(&k) => Some(Transform::Split(k as u32)),
+        _ => None
+    }
+}
+
+fn count_stones(stones: &[Stone]) -> StoneCounts {
+    let mut counts = StoneCounts::new();
+    for &s in stones {
+        *counts.entry(s).or_default() += 1_u32;
+    }
+    counts
+}
+
+fn blink(counts: &StoneCounts, rules: &RuleSet) -> StoneCounts {
+    let mut new_counts = StoneCounts::with_capacity(counts.len());
+    for (&stone, count) in counts {
+        for new_stone in rules.apply(stone) {
+            *new_counts.entry(new_stone).or_default() += count;
+        }
+    }
+    new_counts
+}
+
+fn total(counts: &StoneCounts) -> BigUint {
+    counts.values().sum()
+}
+
+/// Blink over and over, printing how many different numbers there are each step if show_distinct is set
+fn simulate(input: &[Stone], rules: &RuleSet, blinks: usize, show_distinct: bool) -> BigUint {
+    let mut counts = count_stones(input);
+    for step in 1..=blinks {
+        counts = blink(&counts, rules);
+        if show_distinct {
+            println!("Blink {step}: {} distinct numbers", counts.len());
+        }
+    }
+    total(&counts)
+}
+
+fn part1(input: &[Stone], rules: &RuleSet, show_distinct: bool) {
+    println!("{}", simulate(input, rules, 25, show_distinct));
+}
+
+fn part2(input: &[Stone], rules: &RuleSet, show_distinct: bool) {
+    println!("{}", simulate(input, rules, 75, show_distinct));
+}
+
+/// Every number you can ever get to from the starting stones, and the transition matrix between them:
+/// transitions[i] has (j, n) if one stone numbered values[i] turns into n stones numbered values[j].
+/// Nearly every row has one or two entries, so it's stored sparse.
+struct Closure {
+    values: Vec<Stone>,
+    index: HashMap<Stone, usize>,
+    transitions: Vec<Vec<(usize, u64)>>
+}
+
+/// Breadth-first from the starting numbers. Some rule sets never close (e.g. `any => add 1`), so give up after `limit` numbers.
+fn find_closure(input: &[Stone], rules: &RuleSet, limit: usize) -> Option<Closure> {
+    let mut values: Vec<Stone> = Vec::new();
+    let mut index: HashMap<Stone, usize> = HashMap::new();
+    let mut queue: VecDeque<Stone> = VecDeque::new();
+    for &stone in input {
+        index.entry(stone).or_insert_with(|| {
+            values.push(stone);
+            queue.push_back(stone);
+            values.len() - 1
+        });
+    }
+    let mut transitions: Vec<Vec<(usize, u64)>> = Vec::new();
+    while let Some(stone) = queue.pop_front() {
+        let mut row: Vec<(usize, u64)> = Vec::new();
+        for new_stone in rules.apply(stone) {
+            let j = *index.entry(new_stone).or_insert_with(|| {
+                values.push(new_stone);
+                queue.push_back(new_stone);
+                values.len() - 1
+            });
+            match row.iter_mut().find(|(k, _)| *k == j) {
+                Some((_, n)) => *n += 1,
+                None => row.push((j, 1))
+            }
+        }
+        transitions.push(row); // Values get queued in index order, so this lines up
+        if values.len() > limit {
+            return None
+        }
+    }
+    Some(Closure { values, index, transitions })
+}
+
+/// The sets of distinct numbers after each blink have to repeat eventually (there are only so many of them).
+/// Returns (first blink of the repeating part, how often it repeats), so (n, 1) means it stops changing at blink n.
+fn find_stable_point(input: &[Stone], closure: &Closure, max_blinks: usize) -> Option<(usize, usize)> {
+    let mut present: Vec<bool> = vec![false; closure.values.len()];
+    for stone in input {
+        present[closure.index[stone]] = true;
+    }
+    let mut seen: HashMap<Vec<bool>, usize> = HashMap::new();
+    for blink in 0..=max_blinks {
+        if let Some(&first) = seen.get(&present) {
+            return Some((first, blink - first))
+        }
+        let mut next_present: Vec<bool> = vec![false; present.len()];
+        for (i, _) in present.iter().enumerate().filter(|(_, &p)| p) {
+            for &(j, _) in closure.transitions[i].iter() {
+                next_present[j] = true;
+            }
+        }
+        seen.insert(std::mem::replace(&mut present, next_present), blink);
+    }
+    None
+}
+
+fn mul_mod(a: u64, b: u64, prime: u64) -> u64 {
+    ((a as u128 * b as u128) % prime as u128) as u64
+}
+
+fn pow_mod(mut base: u64, mut exponent: u64, prime: u64) -> u64 {
+    let mut result = 1 % prime;
+    base %= prime;
+    while exponent > 0 {
+        if exponent & 1 == 1 {
+            result = mul_mod(result, base, prime);
+        }
+        base = mul_mod(base, base, prime);
+        exponent >>= 1;
+    }
+    result
+}
+
+/// Miller-Rabin with the bases that are enough for anything that fits in a u64
+fn is_prime(n: u64) -> bool {
+    const BASES: [u64; 12] = [2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37];
+    if n < 2 {
+        return false
+    }
+    if let Some(&base) = BASES.iter().find(|&&base| n.is_multiple_of(base)) {
+        return n == base
+    }
+    let (mut odd, mut twos) = (n - 1, 0);
+    while odd.is_multiple_of(2) {
+        odd /= 2;
+        twos += 1;
+    }
+    BASES.iter().all(|&base| {
+        let mut x = pow_mod(base, odd, n);
+        if x == 1 || x == n - 1 {
+            return true
+        }
+        (1..twos).any(|_| {
+            x = mul_mod(x, x, n);
+            x == n - 1
+        })
+    })
+}
+
+/// Total stones after 0, 1, 2... blinks (mod prime), stepping the counts through the transition matrix
+fn totals_mod(input: &[Stone], closure: &Closure, blinks: usize, prime: u64) -> Vec<u64> {
+    let mut counts: Vec<u64> = vec![0; closure.values.len()];
+    for stone in input {
+        let i = closure.index[stone];
+        counts[i] = (counts[i] + 1) % prime;
+    }
+    let mut totals: Vec<u64> = Vec::with_capacity(blinks + 1);
+    for _ in 0..=blinks {
+        totals.push(counts.iter().fold(0, |sum, &count| (sum + count) % prime));
+        let mut next_counts: Vec<u64> = vec![0; counts.len()];
+        for (i, &count) in counts.iter().enumerate().filter(|(_, &count)| count > 0) {
+            for &(j, n) in closure.transitions[i].iter() {
+                next_counts[j] = (next_counts[j] + mul_mod(count, n, prime)) % prime;
+            }
+        }
+        counts = next_counts;
+    }
+    totals
+}
+
+/// The shortest recurrence sequence[n] = c[0] * sequence[n-1] + c[1] * sequence[n-2] + ... (mod prime) that fits
+fn berlekamp_massey(sequence: &[u64], prime: u64) -> Vec<u64> {
+    let mut current: Vec<u64> = Vec::new(); // The recurrence so far
+    let mut before: Vec<u64> = Vec::new(); // The one we had the last time it had to get longer...
+    let mut last_failure: Option<(usize, u64)> = None; // ...and where that was and how far off it was
+    for n in 0..sequence.len() {
+        let predicted = current.iter().enumerate().fold(0, |sum, (i, &c)| (sum + mul_mod(c, sequence[n - 1 - i], prime)) % prime);
+        let error = (sequence[n] + prime - predicted) % prime;
+        if error == 0 {
+            continue
+        }
+        let Some((failed_at, failed_error)) = last_failure else {
+            // Nothing fits up to here except a recurrence that's all zeroes
+            current = vec![0; n + 1];
+            last_failure = Some((n, error));
+            continue
+        };
+        // Fix it up by adding a scaled, shifted copy of the old recurrence, which cancels out the error
+        let scale = mul_mod(error, pow_mod(failed_error, prime - 2, prime), prime);
+        let mut fixed: Vec<u64> = vec![0; n - failed_at - 1];
+        fixed.push(scale);
+        fixed.extend(before.iter().map(|&b| (prime - mul_mod(scale, b, prime)) % prime));
+        if fixed.len() < current.len() {
+            fixed.resize(current.len(), 0);
+        }
+        for (f, &c) in fixed.iter_mut().zip(current.iter()) {
+            *f = (*f + c) % prime;
+        }
+        if n - failed_at + before.len() >= current.len() {
+            before = current;
+            last_failure = Some((n, error));
+        }
+        current = fixed;
+    }
+    current
+}
+
+/// a * b mod (x^L - c[0] x^(L-1) - ... - c[L-1]), for polynomials with L coefficients (lowest power first)
+fn mul_mod_recurrence(a: &[u64], b: &[u64], recurrence: &[u64], prime: u64) -> Vec<u64> {
+    let len = recurrence.len();
+    let mut product: Vec<u64> = vec![0; 2 * len];
+    for (i, &x) in a.iter().enumerate().filter(|(_, &x)| x > 0) {
+        for (j, &y) in b.iter().enumerate() {
+            product[i + j] = (product[i + j] + mul_mod(x, y, prime)) % prime;
+        }
+    }
+    // Swap each x^k (k >= L) for what x^L is equal to, from the top down
+    for k in (len..product.len()).rev() {
+        let top = product[k];
+        if top == 0 {
+            continue
+        }
+        for (i, &c) in recurrence.iter().enumerate() {
+            product[k - 1 - i] = (product[k - 1 - i] + mul_mod(top, c, prime)) % prime;
+        }
+    }
+    product.truncate(len);
+    product
+}
+
+/// Total stones after `blinks` blinks, mod prime. Multiplying by the transition matrix over and over is too slow
+/// when there are thousands of numbers (that's a lot of cubes), but by Cayley-Hamilton the totals follow a
+/// recurrence no longer than the number of values in the closure. So we work out that recurrence from the
+/// first few totals, and raising the matrix to the power `blinks` is the same as working out x^blinks mod the
+/// recurrence's polynomial, which only needs log(blinks) polynomial multiplications.
+fn count_mod(input: &[Stone], closure: &Closure, blinks: u64, prime: u64) -> u64 {
+    let totals = totals_mod(input, closure, 2 * closure.values.len() + 1, prime);
+    if let Some(&total) = usize::try_from(blinks).ok().and_then(|blinks| totals.get(blinks)) {
+        return total
+    }
+    let recurrence = berlekamp_massey(&totals, prime);
+    if recurrence.is_empty() {
+        return 0 // Everything is 0 mod prime from the start
+    }
+    let len = recurrence.len();
+    let mut x: Vec<u64> = vec![0; len];
+    let mut result: Vec<u64> = vec![0; len];
+    if len == 1 {
+        x[0] = recurrence[0] % prime; // x is already x^1 reduced
     } else {
-        let mut stone_count: u64 = 0;
-        for s in new_stones {
-            stone_count += count_stones(s, blinks-1);
+        x[1] = 1;
+    }
+    result[0] = 1;
+    let mut exponent = blinks;
+    while exponent > 0 {
+        if exponent & 1 == 1 {
+            result = mul_mod_recurrence(&result, &x, &recurrence, prime);
         }
-        return stone_count
+        x = mul_mod_recurrence(&x, &x, &recurrence, prime);
+        exponent >>= 1;
     }
+    result.iter().zip(totals.iter()).fold(0, |sum, (&r, &t)| (sum + mul_mod(r, t, prime)) % prime)
 }
 
-fn part1(input: &Vec<i64>) {
-    let mut stones = input.clone();
-    for _ in 0..25 {
-        stones = blink(stones);
+fn write_closure(closure: &Closure, fname: String) -> io::Result<()> {
+    let file = File::create(fname)?;
+    let mut writer = BufWriter::new(file);
+    for (i, row) in closure.transitions.iter().enumerate() {
+        let targets: Vec<String> = row.iter().map(|&(j, n)| if n == 1 { format!("{}", closure.values[j]) } else { format!("{}x{}", n, closure.values[j]) }).collect();
+        writeln!(writer, "{} -> {}", closure.values[i], targets.join(" "))?;
     }
-    println!("{}", stones.len());
+    writer.flush()?;
+    Ok(())
 }
 
-fn part2(input: &Vec<i64>) {
-    let mut stones = input.clone();
-    let mut stone_count: u64 = 0;
-    for s in stones {
-        stone_count += count_stones(s, 75_u32);
+/// e.g. `cargo run -- --analyse --blinks=1000000000000 --prime=1000000007 --save`
+fn analyse(input: &[Stone], rules: &RuleSet) {
+    let arg_value = |prefix: &str| std::env::args().find_map(|arg| arg.strip_prefix(prefix).map(|n| n.parse::<u64>().unwrap_or_else(|_| panic!("Can't parse {arg}"))));
+    let limit = arg_value("--limit=").unwrap_or(100_000) as usize;
+    let Some(closure) = find_closure(input, rules, limit) else {
+        println!("Gave up after {limit} different numbers, these rules might never settle down (try --limit=)");
+        return
+    };
+    let entries: usize = closure.transitions.iter().map(|row| row.len()).sum();
+    println!("{} different numbers can ever show up ({} non-zero transitions)", closure.values.len(), entries);
+    // Every subset of the closure is possible in theory, but it's always been way quicker than this in practice
+    match find_stable_point(input, &closure, 10 * closure.values.len()) {
+        Some((first, 1)) => println!("The set of numbers stops changing after blink {first}"),
+        Some((first, period)) => println!("The set of numbers repeats every {period} blinks from blink {first}"),
+        None => println!("The set of numbers hasn't repeated yet")
+    }
+    if std::env::args().any(|arg| arg == "--save") {
+        if let Err(e) = write_closure(&closure, String::from("closure.txt")) {
+            eprintln!("Failed to write to file: {}", e);
+        }
     }
-    println!("{}", stone_count);
+    if let Some(blinks) = arg_value("--blinks=") {
+        let prime = arg_value("--prime=").unwrap_or(1_000_000_007);
+        if !is_prime(prime) {
+            println!("{prime} isn't prime!");
+            return
+        }
+        println!("{} stones after {blinks} blinks (mod {prime})", count_mod(input, &closure, blinks, prime));
+    }
+}
+
+fn read_rules(filename: &str) -> RuleSet {
+    let contents = fs::read_to_string(filename).expect("Can't read rules file!");
+    RuleSet::parse(&contents).unwrap_or_else(|e| panic!("Bad rules in {filename}: {e}"))
 }
 
+/// e.g. `cargo run -- --blinks=5000 --distinct` to go way further than 75, or `--rules=rules.txt` for different rules
 fn main() {
     let input = read_input("input.txt");
-    part1(&input);
-    part2(&input);
-}
\ No newline at end of file
+    let rules = match std::env::args().find_map(|arg| arg.strip_prefix("--rules=").map(String::from)) {
+        Some(filename) => read_rules(&filename),
+        None => RuleSet::puzzle()
+    };
+    if std::env::args().any(|arg| arg == "--analyse") {
+        analyse(&input, &rules);
+        return
+    }
+    let show_distinct = std::env::args().any(|arg| arg == "--distinct");
+    let blinks = std::env::args().find_map(|arg| arg.strip_prefix("--blinks=").map(|n| n.parse::<usize>().expect("Can't parse --blinks!")));
+    match blinks {
+        Some(blinks) => println!("{}", simulate(&input, &rules, blinks, show_distinct)),
+        None => {
+            part1(&input, &rules, show_distinct);
+            part2(&input, &rules, show_distinct);
+        }
+    }
+}
@@ -1,7 +1,7 @@
-use std::fs;
-use std::collections::HashMap;
-// use std::collections::HashSet; // All these regions SHOULD be HashSets but too late now :)
-use itertools::iproduct;
+use std::fs::{self, File};
+use std::fmt;
+use std::collections::VecDeque;
+use std::io::{self, BufWriter, Write};
 
 fn read_input(filename: &str) -> Vec<Vec<char>> {
     let mut output: Vec<Vec<char>> = Vec::new();
@@ -10,170 +10,465 @@ fn read_input(filename: &str) -> Vec<Vec<char>> {
         let char_array = line.chars().collect();
         output.push(char_array);
     }
-    return output
+    output
 }
 
 struct CharGrid {
     width: usize,
     height: usize,
-    symbols: HashMap<char, Vec<(usize, usize)>>
+    cells: Vec<Vec<char>>
 }
 
 impl CharGrid {
-    fn new(grid_input: &Vec<Vec<char>>) -> CharGrid {
-        let mut grid: CharGrid = CharGrid {
+    fn new(grid_input: &[Vec<char>]) -> CharGrid {
+        let grid: CharGrid = CharGrid {
             // Width and height are "backwards" from how we look at things
             // but consistent with nested vector notation i.e. vec[x][y]
             width: grid_input.len(),
             height: grid_input[0].len(),
-            symbols: HashMap::new()
+            cells: grid_input.to_vec()
         };
+        assert!(grid_input.iter().all(|row| row.len() == grid.height), "Rows aren't all the same length!");
+        grid
+    }
 
-        // Add each unique character as a vector of coordinate tuples
-        for x in 0..grid.width {
-            for y in 0..grid.height {
-                let symbol: char = grid_input[x][y];
-                grid.add_symbol(symbol, (x, y));
-            }
-        }
+    /// The cell one step in some direction, if that's still on the grid (no clamping onto itself!)
+    fn neighbour(&self, (x, y): (usize, usize), (dx, dy): (i32, i32)) -> Option<(usize, usize)> {
+        let next_x = x.checked_add_signed(dx as isize)?;
+        let next_y = y.checked_add_signed(dy as isize)?;
+        if next_x < self.width && next_y < self.height { Some((next_x, next_y)) } else { None }
+    }
+}
+
+const DIRECTIONS: [(i32, i32); 4] = [(-1, 0), (0, 1), (1, 0), (0, -1)]; // Clockwise from up
+
+struct Region {
+    symbol: char,
+    start: (usize, usize), // The first cell we found (top left-ish), to tell regions with the same symbol apart
+    area: u32,
+    perimeter: u32,
+    sides: u32,
+    min: (usize, usize), // Bounding box (inclusive)
+    max: (usize, usize),
+    touches_border: bool,
+    holes: u32, // Patches of other regions that this one completely surrounds...
+    enclosed: Vec<usize> // ...and the ids of the regions in them
+}
+
+/// Which region every cell is in (labels[x][y] is an index into regions, which is the region's id)
+struct Regions {
+    labels: Vec<Vec<usize>>,
+    regions: Vec<Region>
+}
 
-        return grid
+impl Regions {
+    /// None off the edge of the map, so the windows in count_corners can hang off the edges
+    fn label_at(&self, x: isize, y: isize) -> Option<usize> {
+        let row = self.labels.get(usize::try_from(x).ok()?)?;
+        row.get(usize::try_from(y).ok()?).copied()
     }
+}
 
-    fn add_symbol(&mut self, symbol: char, coordinate: (usize, usize)) {
-        if let Some(symbols_vec) = self.symbols.get_mut(&symbol) {
-            symbols_vec.push(coordinate); // Push to vector on exisiting key
-        } else {
-            self.symbols.insert(symbol, vec![coordinate]); // Add new vector at key
+/// Flood fill each region once (the labels double as the visited set), then sweep over the map for the fences and the shape.
+fn label_regions(grid: &CharGrid) -> Regions {
+    const UNLABELLED: usize = usize::MAX;
+    let mut labels: Vec<Vec<usize>> = vec![vec![UNLABELLED; grid.height]; grid.width];
+    let mut regions: Vec<Region> = Vec::new();
+    for x in 0..grid.width {
+        for y in 0..grid.height {
+            if labels[x][y] != UNLABELLED {
+                continue
+            }
+            let id = regions.len();
+            let symbol = grid.cells[x][y];
+            let mut region = Region {
+                symbol, start: (x, y), area: 0, perimeter: 0, sides: 0,
+                min: (x, y), max: (x, y), touches_border: false, holes: 0, enclosed: Vec::new()
+            };
+            labels[x][y] = id;
+            let mut queue: VecDeque<(usize, usize)> = VecDeque::from([(x, y)]);
+            while let Some(cell) = queue.pop_front() {
+                region.area += 1;
+                region.min = (region.min.0.min(cell.0), region.min.1.min(cell.1));
+                region.max = (region.max.0.max(cell.0), region.max.1.max(cell.1));
+                for direction in DIRECTIONS {
+                    match grid.neighbour(cell, direction) {
+                        Some((next_x, next_y)) => {
+                            if labels[next_x][next_y] == UNLABELLED && grid.cells[next_x][next_y] == symbol {
+                                labels[next_x][next_y] = id;
+                                queue.push_back((next_x, next_y));
+                            } else if grid.cells[next_x][next_y] != symbol {
+                                region.perimeter += 1;
+                            }
+                        },
+                        None => {
+                            region.perimeter += 1;
+                            region.touches_border = true;
+                        }
+                    }
+                }
+            }
+            regions.push(region);
         }
     }
+    let mut output = Regions { labels, regions };
+    count_corners(&mut output);
+    for id in 0..output.regions.len() {
+        find_holes(&mut output, id);
+    }
+    output
+}
 
-    fn get_symbols(&self, symbol: char) -> Option<Vec<(usize, usize)>> {
-        return self.symbols.get(&symbol).cloned()
+/// A region has as many sides as corners. Every corner is at a point where four cells meet, so slide a 2x2 window
+/// over all of those points (including the ones on the edge, with the outside as its own thing) and look at the
+/// cells each region has in it. One cell is an outside corner, three is an inside corner, and two diagonally
+/// opposite cells is two corners at once (the region touches itself there). Two cells side by side is just a
+/// straight fence, and four is the middle of the region.
+fn count_corners(regions: &mut Regions) {
+    let width = regions.labels.len() as isize;
+    let height = regions.labels.first().map_or(0, |row| row.len()) as isize;
+    for x in 0..=width {
+        for y in 0..=height {
+            // Clockwise from the top left, so opposite corners are 2 apart
+            let window = [regions.label_at(x - 1, y - 1), regions.label_at(x - 1, y), regions.label_at(x, y), regions.label_at(x, y - 1)];
+            for (i, label) in window.iter().enumerate() {
+                let Some(id) = *label else { continue };
+                if window[..i].contains(label) {
+                    continue // Already counted this region in this window
+                }
+                let mine: Vec<bool> = window.iter().map(|other| *other == Some(id)).collect();
+                let corners = match mine.iter().filter(|&&m| m).count() {
+                    1 | 3 => 1,
+                    2 if mine[0] == mine[2] => 2, // Diagonal
+                    _ => 0
+                };
+                regions.regions[id].sides += corners;
+            }
+        }
     }
 }
 
-// Recursively find contiguous coordinates from a starting coordinate
-fn find_contig_coords(mut current_region: Vec<(usize, usize)>, all_coords: &Vec<(usize, usize)>,) -> Vec<(usize, usize)> {
-    let region_snapshot = current_region.clone(); // Clone the region to avoid borrowing issues
-    for coord in region_snapshot {
-        // Add immediately adjacent coordinates (if they exist and we haven't yet)
-        for (x, y) in vec![(-1, 0), (1, 0), (0, -1), (0, 1)] { 
-            let next_x: usize = (coord.0 as i32 + x).max(0) as usize;
-            let next_y: usize = (coord.1 as i32 + y).max(0) as usize;
-            if (all_coords.contains(&(next_x, next_y))) && (!current_region.contains(&(next_x, next_y))) {
-                current_region.push((next_x, next_y));
-                // Recurse to add coordinates adjacet to those, etc.
-                current_region = find_contig_coords(current_region, all_coords);
+/// Flood fill everything that isn't this region inside its bounding box. Anything that reaches the edge of the box
+/// can get out (everything outside the box isn't this region either), and everything that can't is a hole.
+fn find_holes(regions: &mut Regions, id: usize) {
+    let region = &regions.regions[id];
+    let (min, max) = (region.min, region.max);
+    let mut visited: Vec<Vec<bool>> = vec![vec![false; max.1 - min.1 + 1]; max.0 - min.0 + 1];
+    let mut holes = 0;
+    let mut enclosed: Vec<usize> = Vec::new();
+    for x in min.0..=max.0 {
+        for y in min.1..=max.1 {
+            if regions.labels[x][y] == id || visited[x - min.0][y - min.1] {
+                continue
+            }
+            visited[x - min.0][y - min.1] = true;
+            let mut queue: VecDeque<(usize, usize)> = VecDeque::from([(x, y)]);
+            let mut escapes = false;
+            let mut inside: Vec<usize> = Vec::new();
+            while let Some(cell) = queue.pop_front() {
+                if !inside.contains(&regions.labels[cell.0][cell.1]) {
+                    inside.push(regions.labels[cell.0][cell.1]);
+                }
+                for (dx, dy) in DIRECTIONS {
+                    let next = (cell.0.wrapping_add_signed(dx as isize), cell.1.wrapping_add_signed(dy as isize));
+                    if !(min.0..=max.0).contains(&next.0) || !(min.1..=max.1).contains(&next.1) {
+                        escapes = true;
+                    } else if regions.labels[next.0][next.1] != id && !visited[next.0 - min.0][next.1 - min.1] {
+                        visited[next.0 - min.0][next.1 - min.1] = true;
+                        queue.push_back(next);
+                    }
+                }
+            }
+            if !escapes {
+                holes += 1;
+                enclosed.extend(inside);
             }
         }
     }
-    return current_region
+    enclosed.sort();
+    let region = &mut regions.regions[id];
+    region.holes = holes;
+    region.enclosed = enclosed;
 }
 
-// Split each vector of a single symbol into multiple vectors for each region!
-fn split_regions(mut coordinates: Vec<(usize, usize)>) -> Vec<Vec<(usize, usize)>> {
-    let mut all_regions: Vec<Vec<(usize, usize)>> = Vec::new();
-    while coordinates.len() > 0 {
-        let this_region: Vec<(usize, usize)> = find_contig_coords(vec![coordinates[0]], &coordinates);
-        coordinates.retain(|c| !&this_region.contains(c)); // Remove this section from the vector
-        all_regions.push(this_region);
+fn print_regions(regions: &Regions) {
+    for (id, region) in regions.regions.iter().enumerate() {
+        println!("Region {id} ({}) from ({}, {}) has area {}, perimeter {} and {} sides.",
+            region.symbol, region.start.0, region.start.1, region.area, region.perimeter, region.sides);
+        let border = if region.touches_border { ", touches the border" } else { "" };
+        let enclosed: Vec<String> = region.enclosed.iter().map(|id| id.to_string()).collect();
+        println!("    Box ({}, {}) to ({}, {}){border}, {} holes with regions [{}] in them.",
+            region.min.0, region.min.1, region.max.0, region.max.1, region.holes, enclosed.join(", "));
     }
-    return all_regions
 }
 
-// Helper for find_sides and find_perimeter
-fn get_neighbors(coord: (usize, usize), region: &Vec<(usize, usize)>) -> Vec<(usize, usize)> {
-    let mut neighbors: Vec<(usize, usize)> = Vec::new();
-    for (x, y) in vec![(-1, 0), (1, 0), (0, -1), (0, 1)] {
-        let next_x: usize = (coord.0 as i32 + x).max(0) as usize;
-        let next_y: usize = (coord.1 as i32 + y).max(0) as usize;
-        if (next_x, next_y) != coord { // Ignore itself!
-            if region.contains(&(next_x, next_y)) {
-                neighbors.push((next_x, next_y));
-            }
+/// How much the fence around a region costs. None if the price doesn't make sense (dividing by 0, overflowing...)
+trait Pricing {
+    fn name(&self) -> String;
+    fn price(&self, region: &Region) -> Option<i64>;
+}
+
+/// Part 1
+struct ByPerimeter;
+
+impl Pricing for ByPerimeter {
+    fn name(&self) -> String {
+        String::from("area * perimeter")
+    }
+
+    fn price(&self, region: &Region) -> Option<i64> {
+        Some(region.area as i64 * region.perimeter as i64)
+    }
+}
+
+/// Part 2
+struct BulkDiscount;
+
+impl Pricing for BulkDiscount {
+    fn name(&self) -> String {
+        String::from("area * sides")
+    }
+
+    fn price(&self, region: &Region) -> Option<i64> {
+        Some(region.area as i64 * region.sides as i64)
+    }
+}
+
+/// Anything made of +, -, *, /, brackets, whole numbers and area, perimeter, sides and holes,
+/// e.g. `area * (sides + 2 * holes)`. Division rounds towards 0. The usual order of operations applies.
+struct Formula {
+    text: String,
+    expression: Expression
+}
+
+enum Expression {
+    Number(i64),
+    Variable(Variable),
+    Negate(Box<Expression>),
+    Binary(char, Box<Expression>, Box<Expression>)
+}
+
+#[derive(Clone, Copy)]
+enum Variable {
+    Area,
+    Perimeter,
+    Sides,
+    Holes
+}
+
+#[derive(Debug)]
+enum FormulaError {
+    UnexpectedEnd,
+    Unexpected { position: usize, found: String },
+    UnknownVariable(String)
+}
+
+impl fmt::Display for FormulaError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FormulaError::UnexpectedEnd => write!(f, "it ends too early"),
+            FormulaError::Unexpected { position, found } => write!(f, "didn't expect {found:?} at position {position}"),
+            FormulaError::UnknownVariable(name) => write!(f, "don't know {name:?} (try area, perimeter, sides or holes)")
+        }
+    }
+}
+
+impl Formula {
+    fn parse(text: &str) -> Result<Formula, FormulaError> {
+        let tokens = tokenize(text)?;
+        let mut parser = Parser { tokens, next: 0 };
+        let expression = parser.sum()?;
+        if let Some((position, token)) = parser.tokens.get(parser.next) {
+            return Err(FormulaError::Unexpected { position: *position, found: token.clone() })
         }
+        Ok(Formula { text: text.trim().to_string(), expression })
     }
-    return neighbors
 }
 
-fn find_perimeter(region: &Vec<(usize, usize)>) -> u32 {
-    let mut perimeter: u32 = (region.len() as u32) * 4_u32; // Everything has all four sides!
-    for coord in region {
-        //  Subtract 1 perimeter for each neighbor (no perimeter there!)
-        perimeter -= get_neighbors(*coord, &region).len() as u32;
+impl Pricing for Formula {
+    fn name(&self) -> String {
+        self.text.clone()
+    }
+
+    fn price(&self, region: &Region) -> Option<i64> {
+        self.expression.evaluate(region)
     }
-    return perimeter
 }
 
-fn count_corners(region: &Vec<(usize, usize)>) -> u32 {
-    let mut corners: f32 = 0.0;
-    for coord in region {
-        // Search for internal corners
-        let neighbors: &Vec<(usize, usize)> = &get_neighbors(*coord, &region);
-        if neighbors.len() == 0 {
-            corners += 4.0 // We could just do an early return here! But we won't :)
-        } else if neighbors.len() == 1 {
-            corners += 2.0; // A protrusion creates 2 internal corners
-        } else if neighbors.len() == 2 {
-            if !((neighbors[0].0 == neighbors[1].0) || (neighbors[0].1 == neighbors[1].1)) {
-                // If both neighbors share no coordinates, then it's an internal corner
-                // Otherwise, they're parallel edges!
-                corners += 1.0;
+impl Expression {
+    fn evaluate(&self, region: &Region) -> Option<i64> {
+        match self {
+            Expression::Number(n) => Some(*n),
+            Expression::Variable(variable) => Some(match variable {
+                Variable::Area => region.area,
+                Variable::Perimeter => region.perimeter,
+                Variable::Sides => region.sides,
+                Variable::Holes => region.holes
+            } as i64),
+            Expression::Negate(inner) => inner.evaluate(region)?.checked_neg(),
+            Expression::Binary(op, left, right) => {
+                let (left, right) = (left.evaluate(region)?, right.evaluate(region)?);
+                match op {
+                    '+' => left.checked_add(right),
+                    '-' => left.checked_sub(right),
+                    '*' => left.checked_mul(right),
+                    _ => left.checked_div(right) // Only '/' gets this far, and this is None for / 0
+                }
             }
         }
+    }
+}
 
-        // Search for external corners (each is EXPLICITLY associated with one internal spot)
-        // For each neighbor pair, their intersections (0 (for n1==n2), 1, 2) will be in the region OR NOT!
-        for (n1, n2) in iproduct!(neighbors, neighbors) {
-            let intersect1_inside: bool = region.contains(&(n1.0, n2.1));
-            let intersect2_inside: bool = region.contains(&(n2.0, n1.1));
-            let n_inside: i32 = (intersect1_inside as i32) + (intersect2_inside as i32);
-            assert!((n_inside == 1) || (n_inside == 2)); // I can't imagine a case where it's 0...
-            // Where n1 and n2 are across from each other, n_inside is 2 and we have no external corners
-            if n_inside == 1 {
-                // println!("Found 1 external corner at ({}, {})", coord.0, coord.1);
-                // println!("with neighbors ({}, {}) and ({}, {})", n1.0, n1.1, n2.0, n2.1);
-                // WE'RE DOUBLE COUNTING BECUASE OF HOW IPRODUCT WORKS! 
-                // easiest workaround is to just add 0.5 each time :)
-                corners += 0.5;
+/// Numbers, names and single character symbols, with where they start in the text (for error messages)
+fn tokenize(text: &str) -> Result<Vec<(usize, String)>, FormulaError> {
+    let mut tokens: Vec<(usize, String)> = Vec::new();
+    let chars: Vec<char> = text.chars().collect();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_alphanumeric() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                i += 1;
             }
+            tokens.push((start, chars[start..i].iter().collect()));
+        } else if "+-*/()".contains(c) {
+            tokens.push((i, c.to_string()));
+            i += 1;
+        } else {
+            return Err(FormulaError::Unexpected { position: i, found: c.to_string() })
         }
     }
-    assert!(corners.fract() == 0.0);
-    return corners as u32
+    Ok(tokens)
 }
 
-fn part1(grid: &CharGrid) {
-    let mut price: u32 = 0; 
-    for (_symbol, coords) in &grid.symbols {
-        let regions: Vec<Vec<(usize, usize)>> = split_regions(coords.to_vec());
-        for region in regions {
-            let area: u32 = region.len() as u32;
-            let perimeter: u32 = find_perimeter(&region);
-            price += area * perimeter;
+/// Recursive descent: a sum is products added together, a product is factors multiplied together
+struct Parser {
+    tokens: Vec<(usize, String)>,
+    next: usize
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.next).map(|(_, token)| token.as_str())
+    }
+
+    fn sum(&mut self) -> Result<Expression, FormulaError> {
+        let mut expression = self.product()?;
+        while let Some(op @ ("+" | "-")) = self.peek() {
+            let op = op.chars().next().unwrap();
+            self.next += 1;
+            expression = Expression::Binary(op, Box::new(expression), Box::new(self.product()?));
         }
+        Ok(expression)
+    }
+
+    fn product(&mut self) -> Result<Expression, FormulaError> {
+        let mut expression = self.factor()?;
+        while let Some(op @ ("*" | "/")) = self.peek() {
+            let op = op.chars().next().unwrap();
+            self.next += 1;
+            expression = Expression::Binary(op, Box::new(expression), Box::new(self.factor()?));
+        }
+        Ok(expression)
     }
-    println!("{}", price);
-}
 
-fn part2(grid: &CharGrid) {
-    let mut price: u32 = 0; 
-    for (_symbol, coords) in &grid.symbols {
-        // println!("{}", _symbol);
-        let regions: Vec<Vec<(usize, usize)>> = split_regions(coords.to_vec());
-        for region in regions {
-            let area: u32 = region.len() as u32;
-            let sides: u32 = count_corners(&region);
-            // println!("Region {} has area {} and {} sides.", _symbol, area, sides);
-            price += area * sides;
+    fn factor(&mut self) -> Result<Expression, FormulaError> {
+        let (position, token) = self.tokens.get(self.next).cloned().ok_or(FormulaError::UnexpectedEnd)?;
+        self.next += 1;
+        match token.as_str() {
+            "-" => Ok(Expression::Negate(Box::new(self.factor()?))),
+            "(" => {
+                let inside = self.sum()?;
+                match self.tokens.get(self.next) {
+                    Some((_, close)) if close == ")" => {
+                        self.next += 1;
+                        Ok(inside)
+                    },
+                    Some((position, found)) => Err(FormulaError::Unexpected { position: *position, found: found.clone() }),
+                    None => Err(FormulaError::UnexpectedEnd)
+                }
+            },
+            "area" => Ok(Expression::Variable(Variable::Area)),
+            "perimeter" => Ok(Expression::Variable(Variable::Perimeter)),
+            "sides" => Ok(Expression::Variable(Variable::Sides)),
+            "holes" => Ok(Expression::Variable(Variable::Holes)),
+            _ if token.starts_with(|c: char| c.is_ascii_digit()) => {
+                token.parse().map(Expression::Number).map_err(|_| FormulaError::Unexpected { position, found: token.clone() })
+            },
+            _ if token.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') => Err(FormulaError::UnknownVariable(token)),
+            _ => Err(FormulaError::Unexpected { position, found: token })
         }
     }
-    println!("{}", price);
 }
 
+/// `perimeter` and `sides` are the puzzle's two prices, anything else is a Formula
+fn pricing_from_name(name: &str) -> Result<Box<dyn Pricing>, FormulaError> {
+    match name {
+        "perimeter" => Ok(Box::new(ByPerimeter)),
+        "sides" => Ok(Box::new(BulkDiscount)),
+        _ => Ok(Box::new(Formula::parse(name)?))
+    }
+}
+
+/// Err has the id of the first region that can't be priced
+fn total_price(regions: &Regions, pricing: &dyn Pricing) -> Result<i64, usize> {
+    let mut total: i64 = 0;
+    for (id, region) in regions.regions.iter().enumerate() {
+        total = pricing.price(region).and_then(|price| total.checked_add(price)).ok_or(id)?;
+    }
+    Ok(total)
+}
+
+/// Every region, most expensive first (the empty price is for regions the formula can't price)
+fn write_csv(regions: &Regions, pricing: &dyn Pricing, fname: String) -> io::Result<()> {
+    let mut priced: Vec<(usize, &Region, Option<i64>)> = regions.regions.iter().enumerate()
+        .map(|(id, region)| (id, region, pricing.price(region)))
+        .collect();
+    priced.sort_by_key(|&(id, _, price)| (std::cmp::Reverse(price), id));
+    let file = File::create(fname)?;
+    let mut writer = BufWriter::new(file);
+    writeln!(writer, "id,symbol,area,perimeter,sides,holes,price")?;
+    for (id, region, price) in priced {
+        let price = price.map_or(String::new(), |price| price.to_string());
+        writeln!(writer, "{id},{},{},{},{},{},{price}", region.symbol, region.area, region.perimeter, region.sides, region.holes)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+fn part1(regions: &Regions) {
+    println!("{}", total_price(regions, &ByPerimeter).unwrap());
+}
+
+fn part2(regions: &Regions) {
+    println!("{}", total_price(regions, &BulkDiscount).unwrap());
+}
+
+/// e.g. `cargo run -- --pricing="area * (sides + holes)" --csv=report.csv` (--pricing=perimeter and --pricing=sides are parts 1 and 2)
 fn main() {
     let grid = CharGrid::new(&read_input("input.txt"));
-    part1(&grid);
-    part2(&grid);
-}
\ No newline at end of file
+    let regions = label_regions(&grid);
+    if std::env::args().any(|arg| arg == "--regions") {
+        print_regions(&regions);
+    }
+    part1(&regions);
+    part2(&regions);
+    let pricing = std::env::args().find_map(|arg| arg.strip_prefix("--pricing=").map(|name| {
+        pricing_from_name(name).unwrap_or_else(|e| panic!("Bad pricing {name:?}: {e}"))
+    }));
+    if let Some(pricing) = &pricing {
+        match total_price(&regions, pricing.as_ref()) {
+            Ok(total) => println!("{}: {}", pricing.name(), total),
+            Err(id) => println!("{}: can't price region {id}", pricing.name())
+        }
+    }
+    if let Some(fname) = std::env::args().find_map(|arg| arg.strip_prefix("--csv=").map(String::from)) {
+        let pricing = pricing.unwrap_or_else(|| Box::new(BulkDiscount));
+        if let Err(e) = write_csv(&regions, pricing.as_ref(), fname) {
+            eprintln!("Failed to write to file: {}", e);
+        }
+    }
+}
@@ -1,142 +1,596 @@
+use std::fmt;
 use std::fs;
 use regex::Regex;
 
-fn read_input(filename: &str) -> Vec<String> {
-    let mut input = Vec::new();
+fn read_input(filename: &str) -> Vec<ClawMachine> {
     let contents = fs::read_to_string(filename).expect("Can't read file!");
-    for line in contents.lines() {
-        input.push(line.to_string());
+    parse_machines(&contents).unwrap_or_else(|e| panic!("Can't parse {filename}: {e}"))
+}
+
+#[derive(Debug)]
+enum ParseError {
+    Empty,
+    NoButtons { block: usize },
+    BadLine { line: usize, expected: &'static str, found: String },
+    MissingCost { line: usize, name: String },
+    DuplicateButton { line: usize, name: String },
+    DifferentAxes { line: usize, expected: String, found: String }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::Empty => write!(f, "there aren't any claw machines"),
+            ParseError::NoButtons { block } => write!(f, "machine {block} doesn't have any buttons"),
+            ParseError::BadLine { line, expected, found } => write!(f, "line {line} should look like {expected:?} but it's {found:?}"),
+            ParseError::MissingCost { line, name } => write!(f, "button {name} on line {line} needs a cost (only A and B have one already)"),
+            ParseError::DuplicateButton { line, name } => write!(f, "there's already a button {name} before line {line}"),
+            ParseError::DifferentAxes { line, expected, found } => write!(f, "line {line} has axes {found} but the first line had {expected}")
+        }
     }
-    return input;
 }
 
-fn extract_numbers(input: String) -> (u64, u64) {
-    let re = Regex::new(r"X[+=](?<x>\d+), Y[+=](?<y>\d+)").unwrap();
-    let captures = re.captures(&input).unwrap();
-    return (captures["x"].parse::<u64>().unwrap(), captures["y"].parse::<u64>().unwrap());
+const BUTTON_EXAMPLE: &str = "Button A: X+94, Y+34 (or Button C: X+1, Y+2, Z+3, cost 5, limit 10)";
+const PRIZE_EXAMPLE: &str = "Prize: X=8400, Y=5400";
+
+/// Machines are blocks of lines with blank lines between them (any number of blank lines, and it doesn't
+/// matter if there's one at the end or not any more). Each one is any number of buttons and then the prize.
+/// A and B cost 3 and 1 like in the puzzle, any other buttons need a cost, and any button can have a limit.
+/// There can be any number of axes (one capital letter each), as long as every line in a machine has the same ones.
+fn parse_machines(contents: &str) -> Result<Vec<ClawMachine>, ParseError> {
+    let button = Regex::new(r"^Button (?<name>\w+): (?<axes>[A-Z]\+\d+(, [A-Z]\+\d+)*)(?<options>(, [a-z]+ \d+)*)$").unwrap();
+    let prize = Regex::new(r"^Prize: (?<axes>[A-Z]=\d+(, [A-Z]=\d+)*)$").unwrap();
+
+    let mut machines: Vec<ClawMachine> = Vec::new();
+    let mut block: Vec<(usize, &str)> = Vec::new(); // (line number, line)
+    let lines = contents.lines().map(str::trim).enumerate().map(|(i, line)| (i + 1, line));
+    for (line_number, line) in lines.chain([(0, "")]) { // One more blank line to finish off the last block
+        if !line.is_empty() {
+            block.push((line_number, line));
+            continue
+        }
+        let Some((&(line, text), button_lines)) = block.split_last() else { continue };
+        // Every block ends with its prize, so check that first. Otherwise a block that's lost its prize line
+        // has its last button taken for the prize (and then maybe "doesn't have any buttons").
+        let bad_line = || ParseError::BadLine { line, expected: PRIZE_EXAMPLE, found: text.to_string() };
+        let captures = prize.captures(text).ok_or_else(bad_line)?;
+        let (prize_axes, prize) = parse_axes(&captures["axes"], '=').ok_or_else(bad_line)?;
+        if button_lines.is_empty() {
+            return Err(ParseError::NoButtons { block: machines.len() + 1 })
+        }
+        let mut axes: Option<Vec<char>> = None;
+        let mut same_axes = |line: usize, found: Vec<char>| -> Result<(), ParseError> {
+            let expected = axes.get_or_insert_with(|| found.clone());
+            if *expected != found {
+                let format = |axes: &[char]| axes.iter().map(char::to_string).collect::<Vec<String>>().join(", ");
+                return Err(ParseError::DifferentAxes { line, expected: format(expected), found: format(&found) })
+            }
+            Ok(())
+        };
+        let mut buttons: Vec<Button> = Vec::new();
+        for &(line, text) in button_lines {
+            let (button, button_axes) = parse_button(&button, line, text)?;
+            same_axes(line, button_axes)?;
+            if buttons.iter().any(|other| other.name == button.name) {
+                return Err(ParseError::DuplicateButton { line, name: button.name })
+            }
+            buttons.push(button);
+        }
+        same_axes(line, prize_axes.clone())?;
+        machines.push(ClawMachine { axes: prize_axes, buttons, prize });
+        block.clear();
+    }
+    if machines.is_empty() {
+        return Err(ParseError::Empty)
+    }
+    Ok(machines)
 }
 
+/// "X+94, Y+34" into (['X', 'Y'], [94, 34]). None if a number's too big.
+fn parse_axes(text: &str, separator: char) -> Option<(Vec<char>, Vec<u64>)> {
+    text.split(", ").map(|axis| {
+        let (name, value) = axis.split_once(separator)?;
+        Some((name.chars().next()?, value.parse::<u64>().ok()?))
+    }).collect::<Option<Vec<(char, u64)>>>().map(|axes| axes.into_iter().unzip())
+}
+
+fn parse_button(re: &Regex, line: usize, text: &str) -> Result<(Button, Vec<char>), ParseError> {
+    let bad_line = || ParseError::BadLine { line, expected: BUTTON_EXAMPLE, found: text.to_string() };
+    let captures = re.captures(text).ok_or_else(bad_line)?;
+    let name = captures["name"].to_string();
+    let (axes, moves) = parse_axes(&captures["axes"], '+').ok_or_else(bad_line)?;
+    let mut cost = match name.as_str() {
+        "A" => Some(A_COST),
+        "B" => Some(B_COST),
+        _ => None
+    };
+    let mut limit = None;
+    for option in captures["options"].split(", ").filter(|option| !option.is_empty()) {
+        let (key, value) = option.split_once(' ').ok_or_else(bad_line)?;
+        let value = value.parse::<u64>().map_err(|_| bad_line())?;
+        match key {
+            "cost" => cost = Some(value),
+            "limit" => limit = Some(value),
+            _ => return Err(bad_line())
+        }
+    }
+    let cost = cost.ok_or(ParseError::MissingCost { line, name: name.clone() })?;
+    Ok((Button { name, moves, cost, limit }, axes))
+}
+
+const A_COST: u64 = 3;
+const B_COST: u64 = 1;
+
+#[derive(Clone)]
+struct Button {
+    name: String,
+    moves: Vec<u64>, // How far it goes along each axis
+    cost: u64, // Tokens per press
+    limit: Option<u64> // Most times it can be pressed
+}
+
+#[derive(Clone)]
 struct ClawMachine {
-    a: (u64, u64), // 3 token cost
-    b: (u64, u64), // 1 token cost
-    prize: (u64, u64)
+    axes: Vec<char>, // Names of the axes (X, Y, Z...)
+    buttons: Vec<Button>,
+    prize: Vec<u64>
 }
 
 impl ClawMachine {
-    fn new(a_string: &String, b_string: &String, prize_string: &String) -> ClawMachine {
-        ClawMachine {
-            a: extract_numbers(a_string.to_string()),
-            b: extract_numbers(b_string.to_string()),
-            prize: extract_numbers(prize_string.to_string())
-        }
-    }
-
-    // Part 1 gives us a hint that there's a maximum mumber of presses
-    // No need to do lin alg... yet, I assume...
-    fn find_solutions_naively(self, max_presses: u64) -> Vec<(u64, u64)> {
-        let mut solutions: Vec<(u64, u64)> = Vec::new();
-        'a_loop: for a_presses in 0..=max_presses {
-            'b_loop: for b_presses in 0..=max_presses {
-                let location: (u64, u64) = (a_presses*self.a.0 + b_presses*self.b.0, a_presses*self.a.1 + b_presses*self.b.1);
-                if location == self.prize {
-                    solutions.push((a_presses as u64, b_presses as u64));
-                    break 'b_loop // No reason to keep increasing b
-                } else if (location.0 > self.prize.0) || (location.1 > self.prize.1) {
-                    if b_presses == 0 {
-                        break 'a_loop // a has already gotten too large!
-                    } else {
-                        break 'b_loop // b has gotten too large!
+    /// How many times to press each button to win for the fewest tokens, if you can win at all.
+    /// `press_limit` applies to every button on top of their own limits (part 1 has one of 100).
+    fn cheapest_presses(&self, press_limit: Option<u64>) -> Option<Vec<u64>> {
+        let limits: Vec<Option<i128>> = self.buttons.iter().map(|button| {
+            match (button.limit, press_limit) {
+                (Some(a), Some(b)) => Some(a.min(b) as i128),
+                (limit, None) | (None, limit) => limit.map(|limit| limit as i128)
+            }
+        }).collect();
+        let mut search = Search::new(self, limits)?;
+        let mut presses: Vec<i128> = vec![0; self.buttons.len()];
+        search.branch(0, to_i128(&self.prize), 0, &mut presses);
+        search.best.map(|(_, presses)| presses.into_iter().map(|p| p as u64).collect())
+    }
+
+    fn cost(&self, presses: &[u64]) -> u64 {
+        self.buttons.iter().zip(presses.iter()).map(|(button, presses)| button.cost * presses).sum()
+    }
+}
+
+/// Minimising the cost is an integer linear program, but a small one. Pick as many buttons as we can that all
+/// point in different directions (linearly independent), and call them the leaves: once every other button has
+/// a number of presses, there's at most one way to press the leaves to finish the job, and it can be worked out
+/// exactly with integer Gaussian elimination (see Leaves). So we branch on the presses for all the others, and
+/// skip any branch that can't beat the best answer so far.
+///
+/// The last button we branch on doesn't need every count tried either: the leaf presses are a straight line in
+/// its count, so which counts work is a few modular equations and some bounds, and the cost is a straight line
+/// too (see solve_last). So up to one more button than the leaves (like the puzzle's, even if they point the same
+/// way) is instant even with part 2's huge prizes. Any more than that only get tried close to the cheapest
+/// fractional answer (see Search::new), which is quick for a few small buttons but gets slow with lots of big ones.
+struct Search<'a> {
+    buttons: &'a [Button],
+    limits: Vec<Option<i128>>,
+    branch_order: Vec<usize>, // Buttons we try each number of presses for...
+    leaves: Leaves, // ...and the ones we work out from what's left
+    cheapest_per_move: Vec<Vec<Option<(i128, i128)>>>, // Lowest cost/move on each axis (as a fraction) from branch_order[depth..] and the leaves
+    gcd_per_axis: Vec<Vec<i128>>, // GCD of the moves on each axis from the same buttons (anything else can't be reached!)
+    windows: Vec<(i128, i128)>, // Fewest and most presses worth trying for each button (see Search::new)
+    best: Option<(i128, Vec<i128>)>
+}
+
+impl<'a> Search<'a> {
+    /// None if the prize can't be reached even with fractional presses
+    fn new(machine: &'a ClawMachine, limits: Vec<Option<i128>>) -> Option<Search<'a>> {
+        let buttons = &machine.buttons;
+        // Buttons that don't move the claw (or can't be pressed at all) just stay at 0. They definitely shouldn't
+        // take the closed-form spot at the end, or the button that does the work gets every count tried instead!
+        let mut usable: Vec<usize> = (0..buttons.len())
+            .filter(|&i| buttons[i].moves.iter().any(|&m| m > 0) && limits[i] != Some(0))
+            .collect();
+        // Leaves (and then the last button) can take any number of presses for free, so give them the unlimited ones
+        usable.sort_by_key(|&i| limits[i].is_some());
+        let leaves = Leaves::new(buttons, &usable, machine.axes.len());
+        // Limited buttons first, since those have the fewest options, and then big moves first for the same reason
+        let mut branch_order: Vec<usize> = usable.iter().copied().filter(|i| !leaves.buttons.contains(i)).collect();
+        branch_order.sort_by_key(|&i| (limits[i].is_none(), std::cmp::Reverse(buttons[i].moves.iter().sum::<u64>())));
+
+        let mut cheapest_per_move: Vec<Vec<Option<(i128, i128)>>> = vec![vec![None; machine.axes.len()]; branch_order.len() + 1];
+        let mut gcd_per_axis: Vec<Vec<i128>> = vec![vec![0; machine.axes.len()]; branch_order.len() + 1];
+        for depth in (0..=branch_order.len()).rev() {
+            let mut cheapest = if depth < branch_order.len() { cheapest_per_move[depth + 1].clone() } else { vec![None; machine.axes.len()] };
+            let mut gcds = if depth < branch_order.len() { gcd_per_axis[depth + 1].clone() } else { vec![0; machine.axes.len()] };
+            let newly_available: &[usize] = if depth < branch_order.len() { &branch_order[depth..=depth] } else { &leaves.buttons };
+            for &i in newly_available {
+                let button = &buttons[i];
+                for (axis, &moves) in button.moves.iter().enumerate() {
+                    gcds[axis] = gcd(gcds[axis], moves as i128);
+                    let ratio = (button.cost as i128, moves as i128);
+                    if moves > 0 && cheapest[axis].is_none_or(|(cost, best_moves)| ratio.0 * best_moves < cost * ratio.1) {
+                        cheapest[axis] = Some(ratio);
                     }
                 }
             }
+            cheapest_per_move[depth] = cheapest;
+            gcd_per_axis[depth] = gcds;
         }
-        return solutions
+
+        // Some cheapest answer is always within (number of buttons) * (biggest subdeterminant) presses of the
+        // cheapest fractional answer on every button (Cook, Gerards, Schrijver and Tardos), so there's no point
+        // trying 10^13 presses when the fractional answer says 20!
+        let fractional = relaxation(buttons, &usable, &limits, &to_i128(&machine.prize))?;
+        let spread = usable.len() as i128 * largest_subdeterminant(buttons, &usable, machine.axes.len());
+        let windows: Vec<(i128, i128)> = fractional.iter().map(|&(presses, denominator)| {
+            (ceil_div(presses - spread * denominator, denominator).max(0), floor_div(presses + spread * denominator, denominator))
+        }).collect();
+        Some(Search { buttons, limits, branch_order, leaves, cheapest_per_move, gcd_per_axis, windows, best: None })
     }
 
-    fn find_solutions_with_math(self) -> Vec<(u64, u64)> {
-        // I didn't even do linear algebra since it's a 2x2 matrix. I just set up a system of equations.
-        // But how do I know that there's only one solution? I determine linear dependence?
-        // But why does doing a system of equations return one unambiguous answer?
-        let b_presses_numerator: i64 = ((self.a.0 * self.prize.1) as i64) - ((self.a.1 * self.prize.0) as i64);
-        let b_presses_denominator: i64 = ((self.a.0 * self.b.1) as i64) - ((self.a.1 * self.b.0) as i64);
-        let b_presses_float: f64 = (b_presses_numerator as f64)/(b_presses_denominator as f64);
-        let b_presses: u64;
-        if b_presses_float.fract() == 0.0 {
-            b_presses = b_presses_float as u64;
-        } else { // No integer solutions found!
-            return vec![]
+    /// The least it could possibly cost to move `remaining` with the buttons from this depth on, or None if it's impossible.
+    /// Every press moves at most 1/(cheapest cost per move) along each axis, whatever mix of buttons you use.
+    fn lower_bound(&self, depth: usize, remaining: &[i128]) -> Option<i128> {
+        let mut bound = 0;
+        for (axis, &remaining) in remaining.iter().enumerate() {
+            if remaining == 0 {
+                continue
+            }
+            let (cost, moves) = self.cheapest_per_move[depth][axis]?;
+            bound = bound.max(ceil_div(remaining * cost, moves));
         }
+        Some(bound)
+    }
 
-        let a_presses_numerator: i64 = (self.prize.0 as i64) - ((self.b.0 * b_presses) as i64);
-        let a_presses_float: f64 = (a_presses_numerator as f64)/(self.a.0 as f64);
-        let a_presses: u64;
-        if a_presses_float.fract() == 0.0 {
-            a_presses = a_presses_float as u64;
-        } else { // No integer solutions found!
-            return vec![]
+    fn branch(&mut self, depth: usize, remaining: Vec<i128>, cost: i128, presses: &mut Vec<i128>) {
+        if remaining.iter().any(|&r| r < 0) {
+            return // Buttons only go forwards
+        }
+        // Without this, a machine with no answer tries every single count on every button (forever, in part 2)
+        if remaining.iter().zip(self.gcd_per_axis[depth].iter()).any(|(&r, &g)| g != 0 && r % g != 0) {
+            return
+        }
+        let Some(bound) = self.lower_bound(depth, &remaining) else { return };
+        if self.best.as_ref().is_some_and(|(best, _)| cost + bound >= *best) {
+            return
         }
+        if depth + 1 >= self.branch_order.len() {
+            self.solve_last(self.branch_order.get(depth).copied(), &remaining, cost, presses);
+            return
+        }
+        let i = self.branch_order[depth];
+        let button = &self.buttons[i];
+        let moves = to_i128(&button.moves);
+        let (fewest, most) = (self.windows[i].0, self.most_presses(i, &remaining).min(self.windows[i].1));
+        let button_cost = button.cost as i128;
+        for count in fewest..=most {
+            presses[i] = count;
+            let next_remaining: Vec<i128> = remaining.iter().zip(moves.iter()).map(|(r, m)| r - m * count).collect();
+            self.branch(depth + 1, next_remaining, cost + button_cost * count, presses);
+        }
+        presses[i] = 0;
+    }
+
+    /// Can't press it more than this without going past the prize on some axis
+    fn most_presses(&self, i: usize, remaining: &[i128]) -> i128 {
+        let moves = &self.buttons[i].moves;
+        let mut most = self.limits[i].unwrap_or(i128::MAX);
+        for (&m, &r) in moves.iter().zip(remaining.iter()) {
+            if m > 0 {
+                most = most.min(r / m as i128);
+            }
+        }
+        most
+    }
 
-        return vec![(a_presses, b_presses)]
+    /// The cheapest count for the last button we branch on (if there is one) along with the leaves' presses.
+    /// Leaves::solve gives the leaf presses as (u - v * count) / determinant, so for each leaf that has to be a whole
+    /// number (a congruence for count) and between 0 and the leaf's limit (a range for count). Then the total cost is
+    /// a straight line in count, so it's cheapest at one end or the other.
+    fn solve_last(&mut self, last: Option<usize>, remaining: &[i128], cost: i128, presses: &mut [i128]) {
+        let last_moves = last.map_or(vec![0; remaining.len()], |i| to_i128(&self.buttons[i].moves));
+        let (u, v, determinant) = self.leaves.solve(remaining, &last_moves);
 
+        let (mut lowest, mut highest) = last.map_or((0, 0), |i| (self.windows[i].0, self.most_presses(i, remaining).min(self.windows[i].1)));
+        let mut congruence = Some((0, 1)); // count = first (mod step)
+        for (k, &leaf) in self.leaves.buttons.iter().enumerate() {
+            congruence = congruence.and_then(|c| combine_congruence(c, v[k], u[k], determinant));
+            // 0 <= (u - v * count) / determinant <= limit
+            let limit = self.limits[leaf].map(|limit| u[k] - limit * determinant); // v * count >= this
+            match v[k].signum() {
+                1 => {
+                    highest = highest.min(floor_div(u[k], v[k]));
+                    if let Some(limit) = limit {
+                        lowest = lowest.max(ceil_div(limit, v[k]));
+                    }
+                },
+                -1 => {
+                    lowest = lowest.max(ceil_div(-u[k], -v[k]));
+                    if let Some(limit) = limit {
+                        highest = highest.min(floor_div(-limit, -v[k]));
+                    }
+                },
+                _ => if u[k] < 0 || limit.is_some_and(|limit| limit > 0) {
+                    return
+                }
+            }
+        }
+        let Some((first, step)) = congruence else { return };
+        if lowest > highest {
+            return
+        }
+        // How much the total goes up (times the determinant) for each extra press of the last button
+        let slope = last.map_or(0, |i| self.buttons[i].cost as i128) * determinant
+            - self.leaves.buttons.iter().zip(v.iter()).map(|(&leaf, v)| self.buttons[leaf].cost as i128 * v).sum::<i128>();
+        let count = if slope >= 0 {
+            lowest + (first - lowest).rem_euclid(step)
+        } else {
+            highest - (highest - first).rem_euclid(step)
+        };
+        if count < lowest || count > highest {
+            return
+        }
+
+        let mut all_presses = presses.to_vec();
+        if let Some(i) = last {
+            all_presses[i] = count;
+        }
+        for (k, &leaf) in self.leaves.buttons.iter().enumerate() {
+            all_presses[leaf] = (u[k] - v[k] * count) / determinant;
+        }
+        // The leaves only had to match on some of the axes, so check they really get there on the rest
+        let reached: Vec<i128> = (0..remaining.len()).map(|axis| {
+            last_moves[axis] * count + self.leaves.buttons.iter().map(|&leaf| self.buttons[leaf].moves[axis] as i128 * all_presses[leaf]).sum::<i128>()
+        }).collect();
+        if reached != remaining {
+            return
+        }
+        let total = cost + last.map_or(0, |i| self.buttons[i].cost as i128 * count)
+            + self.leaves.buttons.iter().map(|&leaf| self.buttons[leaf].cost as i128 * all_presses[leaf]).sum::<i128>();
+        if self.best.as_ref().is_none_or(|(best, _)| total < *best) {
+            self.best = Some((total, all_presses));
+        }
     }
 }
 
-fn part1(input: &Vec<String>) {
-    let mut tokens: u64 = 0;
-    for i in 0..input.len()/4 {
-        let line_number: usize = i*4;
-        let a_string = &input[line_number];
-        let b_string = &input[line_number+1];
-        let prize_string = &input[line_number+2];
-        let machine = ClawMachine::new(a_string, b_string, prize_string);
-        let solutions = machine.find_solutions_naively(100);
-        if !solutions.is_empty() {
-            let mut lowest_cost: u64 = (solutions[0].0 * 3) + solutions[0].1;
-            for s in solutions {
-                let cost: u64 = (s.0*3) + s.1;
-                if cost < lowest_cost {
-                    lowest_cost = cost;
-                }
+/// The most buttons we can pick that are all linearly independent, and the same number of axes where they still
+/// are, which makes a square system with a non-zero determinant. Solving it with the adjugate (the matrix of
+/// cofactors, so inverse * determinant) keeps everything in integers until the very end.
+struct Leaves {
+    buttons: Vec<usize>,
+    axes: Vec<usize>,
+    adjugate: Vec<Vec<i128>>,
+    determinant: i128
+}
+
+impl Leaves {
+    /// Picks leaves greedily from `candidates`, in that order
+    fn new(buttons: &[Button], candidates: &[usize], axis_count: usize) -> Leaves {
+        let mut leaves: Vec<usize> = Vec::new();
+        for &i in candidates {
+            let mut columns: Vec<Vec<i128>> = leaves.iter().map(|&j| to_i128(&buttons[j].moves)).collect();
+            columns.push(to_i128(&buttons[i].moves));
+            if rank(&columns) == columns.len() {
+                leaves.push(i);
+            }
+        }
+        let mut axes: Vec<usize> = Vec::new();
+        for axis in 0..axis_count {
+            let mut rows: Vec<Vec<i128>> = axes.iter().map(|&a| leaves.iter().map(|&j| buttons[j].moves[a] as i128).collect()).collect();
+            rows.push(leaves.iter().map(|&j| buttons[j].moves[axis] as i128).collect());
+            if rank(&rows) == rows.len() {
+                axes.push(axis);
             }
-            tokens += lowest_cost;
         }
+        let matrix: Vec<Vec<i128>> = axes.iter().map(|&a| leaves.iter().map(|&j| buttons[j].moves[a] as i128).collect()).collect();
+        let mut determinant = determinant(&matrix);
+        let mut adjugate = adjugate(&matrix);
+        if determinant < 0 {
+            determinant = -determinant;
+            adjugate.iter_mut().flatten().for_each(|x| *x = -*x);
+        }
+        Leaves { buttons: leaves, axes, adjugate, determinant }
+    }
+
+    /// Leaf presses that get `remaining - count * last_moves` on our axes are (u - v * count) / determinant
+    fn solve(&self, remaining: &[i128], last_moves: &[i128]) -> (Vec<i128>, Vec<i128>, i128) {
+        let times_adjugate = |vector: &[i128]| -> Vec<i128> {
+            self.adjugate.iter().map(|row| row.iter().zip(self.axes.iter()).map(|(a, &axis)| a * vector[axis]).sum()).collect()
+        };
+        (times_adjugate(remaining), times_adjugate(last_moves), self.determinant)
     }
-    println!("{}", tokens);
 }
 
-// THIS IS SO WEIRD!
-// For some reason, part 2 fails to parse the full input (misses the last claw machine)
-// I didn't feel like debugging, so I just added a newline to the file.
-// Part 1 parses it fine? Oh wait, it probably doesn't, but in part 1 that last machine probably had no soln.
-fn part2(input: &Vec<String>) {
-    let mut tokens: u64 = 0;
-    for i in 0..input.len()/4 {
-        let line_number: usize = i*4;
-        let a_string = &input[line_number];
-        let b_string = &input[line_number+1];
-        let prize_string = &input[line_number+2];
-        let mut machine = ClawMachine::new(a_string, b_string, prize_string);
-        // Add the conversion error here!
-        machine.prize.0 += 10000000000000;
-        machine.prize.1 += 10000000000000;
-        dbg!(machine.prize);
-        let solutions = machine.find_solutions_with_math();
-        if !solutions.is_empty() {
-            let mut lowest_cost: u64 = (solutions[0].0 * 3) + solutions[0].1;
-            for s in solutions {
-                let cost: u64 = (s.0*3) + s.1;
-                if cost < lowest_cost {
-                    lowest_cost = cost;
-                }
+/// The cheapest way to win if we could press buttons fractional numbers of times (the linear program), as
+/// (presses * denominator, denominator) for every button, or None if even that can't get the prize.
+/// It's always at a corner: some independent buttons (a basis) make up the difference while every other button
+/// is pressed 0 or limit times. There aren't many corners with this few buttons, so just try them all!
+fn relaxation(buttons: &[Button], usable: &[usize], limits: &[Option<i128>], prize: &[i128]) -> Option<Vec<(i128, i128)>> {
+    let rank = rank(&usable.iter().map(|&i| to_i128(&buttons[i].moves)).collect::<Vec<Vec<i128>>>());
+    let no_moves = vec![0; prize.len()];
+    let mut best: Option<(i128, i128)> = None; // (cost * denominator, denominator)
+    let mut best_presses: Vec<(i128, i128)> = Vec::new();
+    for basis_mask in 0..1usize << usable.len() {
+        if basis_mask.count_ones() as usize != rank {
+            continue
+        }
+        let basis: Vec<usize> = usable.iter().enumerate().filter(|(k, _)| basis_mask >> k & 1 == 1).map(|(_, &i)| i).collect();
+        let leaves = Leaves::new(buttons, &basis, prize.len());
+        if leaves.buttons.len() < rank {
+            continue
+        }
+        let limited: Vec<usize> = usable.iter().copied().filter(|i| !basis.contains(i) && limits[*i].is_some()).collect();
+        for limit_mask in 0..1usize << limited.len() {
+            let mut presses: Vec<(i128, i128)> = vec![(0, leaves.determinant); buttons.len()];
+            let mut remaining: Vec<i128> = prize.to_vec();
+            for (_, &i) in limited.iter().enumerate().filter(|(k, _)| limit_mask >> k & 1 == 1) {
+                let limit = limits[i].unwrap();
+                presses[i].0 = limit * leaves.determinant;
+                remaining.iter_mut().zip(buttons[i].moves.iter()).for_each(|(r, &m)| *r -= m as i128 * limit);
+            }
+            let (u, _, determinant) = leaves.solve(&remaining, &no_moves);
+            if leaves.buttons.iter().zip(u.iter()).any(|(&leaf, &u)| u < 0 || limits[leaf].is_some_and(|limit| u > limit * determinant)) {
+                continue
+            }
+            // The basis only has to match on some of the axes, so check the rest
+            let reaches = (0..prize.len()).all(|axis| {
+                leaves.buttons.iter().zip(u.iter()).map(|(&leaf, u)| buttons[leaf].moves[axis] as i128 * u).sum::<i128>() == remaining[axis] * determinant
+            });
+            if !reaches {
+                continue
+            }
+            for (&leaf, &u) in leaves.buttons.iter().zip(u.iter()) {
+                presses[leaf].0 = u;
+            }
+            let cost: i128 = presses.iter().zip(buttons.iter()).map(|((p, _), button)| p * button.cost as i128).sum();
+            // Each basis has its own denominator, so compare cost / determinant
+            if best.is_none_or(|(best_cost, best_determinant)| cost * best_determinant < best_cost * determinant) {
+                best = Some((cost, determinant));
+                best_presses = presses;
+            }
+        }
+    }
+    best.map(|_| best_presses)
+}
+
+/// Biggest determinant (in size) of any square piece of the buttons' moves
+fn largest_subdeterminant(buttons: &[Button], usable: &[usize], axis_count: usize) -> i128 {
+    let mut largest = 1;
+    for axis_mask in 1..1usize << axis_count {
+        let axes: Vec<usize> = (0..axis_count).filter(|a| axis_mask >> a & 1 == 1).collect();
+        for button_mask in 1..1usize << usable.len() {
+            if button_mask.count_ones() as usize != axes.len() {
+                continue
+            }
+            let matrix: Vec<Vec<i128>> = axes.iter().map(|&a| {
+                usable.iter().enumerate().filter(|(k, _)| button_mask >> k & 1 == 1).map(|(_, &i)| buttons[i].moves[a] as i128).collect()
+            }).collect();
+            largest = largest.max(determinant(&matrix).abs());
+        }
+    }
+    largest
+}
+
+/// Fraction-free row reduction (each row gets divided back down by its gcd so nothing blows up)
+fn rank(rows: &[Vec<i128>]) -> usize {
+    let mut rows: Vec<Vec<i128>> = rows.to_vec();
+    let columns = rows.first().map_or(0, |row| row.len());
+    let mut rank = 0;
+    for column in 0..columns {
+        let Some(pivot) = (rank..rows.len()).find(|&r| rows[r][column] != 0) else { continue };
+        rows.swap(rank, pivot);
+        for r in rank+1..rows.len() {
+            let (top, this) = (rows[rank][column], rows[r][column]);
+            if this == 0 {
+                continue
+            }
+            let pivot_row = rows[rank].clone();
+            for (x, p) in rows[r].iter_mut().zip(pivot_row.iter()) {
+                *x = *x * top - p * this;
+            }
+            let g = rows[r].iter().fold(0, |g, &x| gcd(g, x));
+            if g > 1 {
+                rows[r].iter_mut().for_each(|x| *x /= g);
+            }
+        }
+        rank += 1;
+    }
+    rank
+}
+
+/// Bareiss elimination, which divides exactly at every step so it never needs fractions
+fn determinant(matrix: &[Vec<i128>]) -> i128 {
+    let n = matrix.len();
+    let mut m: Vec<Vec<i128>> = matrix.to_vec();
+    let (mut sign, mut previous) = (1, 1);
+    for k in 0..n {
+        if m[k][k] == 0 {
+            let Some(swap) = (k+1..n).find(|&r| m[r][k] != 0) else { return 0 };
+            m.swap(k, swap);
+            sign = -sign;
+        }
+        for i in k+1..n {
+            for j in k+1..n {
+                m[i][j] = (m[i][j] * m[k][k] - m[i][k] * m[k][j]) / previous;
             }
-            tokens += lowest_cost;
         }
+        previous = m[k][k];
+    }
+    if n == 0 { 1 } else { sign * m[n-1][n-1] }
+}
+
+/// adjugate[i][j] is the (j, i) cofactor
+fn adjugate(matrix: &[Vec<i128>]) -> Vec<Vec<i128>> {
+    let n = matrix.len();
+    if n == 1 {
+        return vec![vec![1]]
     }
-    println!("{}", tokens);
+    (0..n).map(|i| (0..n).map(|j| {
+        let minor: Vec<Vec<i128>> = (0..n).filter(|&r| r != j).map(|r| {
+            (0..n).filter(|&c| c != i).map(|c| matrix[r][c]).collect()
+        }).collect();
+        if (i + j) % 2 == 0 { determinant(&minor) } else { -determinant(&minor) }
+    }).collect()).collect()
+}
+
+/// Narrow count = first (mod step) down to the counts where (u - v * count) is also a multiple of modulus
+fn combine_congruence((first, step): (i128, i128), v: i128, u: i128, modulus: i128) -> Option<(i128, i128)> {
+    // count = first + step * s, so we need v * step * s = u - v * first (mod modulus)
+    let a = (v * step).rem_euclid(modulus);
+    let b = (u - v * first).rem_euclid(modulus);
+    let (g, inverse, _) = extended_gcd(a, modulus);
+    if b % g != 0 {
+        return None
+    }
+    let reduced = modulus / g;
+    let s = ((b / g) * inverse).rem_euclid(reduced);
+    Some(((first + step * s).rem_euclid(step * reduced), step * reduced))
+}
+
+fn to_i128(v: &[u64]) -> Vec<i128> {
+    v.iter().map(|&x| x as i128).collect()
+}
+
+fn floor_div(numerator: i128, denominator: i128) -> i128 {
+    numerator.div_euclid(denominator) // Only for positive denominators!
+}
+
+fn ceil_div(numerator: i128, denominator: i128) -> i128 {
+    -(-numerator).div_euclid(denominator)
+}
+
+fn gcd(a: i128, b: i128) -> i128 {
+    extended_gcd(a.abs(), b.abs()).0
+}
+
+/// (gcd, x, y) with a*x + b*y = gcd
+fn extended_gcd(a: i128, b: i128) -> (i128, i128, i128) {
+    if b == 0 {
+        (a, 1, 0)
+    } else {
+        let (g, x, y) = extended_gcd(b, a % b);
+        (g, y, x - (a / b) * y)
+    }
+}
+
+fn total_tokens(input: &[ClawMachine], press_limit: Option<u64>) -> u64 {
+    input.iter().filter_map(|machine| machine.cheapest_presses(press_limit).map(|presses| machine.cost(&presses))).sum()
+}
+
+// Part 1 gives us a hint that there's a maximum mumber of presses
+fn part1(input: &[ClawMachine]) {
+    println!("{}", total_tokens(input, Some(100)));
+}
+
+fn part2(input: &[ClawMachine]) {
+    let mut machines: Vec<ClawMachine> = input.to_vec();
+    for machine in machines.iter_mut() {
+        // Add the conversion error here!
+        machine.prize.iter_mut().for_each(|axis| *axis += 10000000000000);
+    }
+    println!("{}", total_tokens(&machines, None));
 }
 
 fn main() {
-    let input: Vec<String> = read_input("input.txt");
+    let input: Vec<ClawMachine> = read_input("input.txt");
     part1(&input);
     part2(&input);
-}
\ No newline at end of file
+}